@@ -0,0 +1,486 @@
+//! Assembler for the mnemonic syntax [`crate::Instruction`] displays and
+//! the disassembler prints. Source is line based:
+//!
+//! ```text
+//! ; comments run to the end of the line
+//! SPEED = 4               ; constants
+//! include "sprites.asm"   ; paths are relative to the including file
+//! start:  LD V0, 0x00
+//!         ADD V0, SPEED
+//!         LD I, ball
+//!         DRW V0, V1, $5
+//!         JMP start
+//! ball:   db 0x20, 0x70, 0xF8, 0x70, 0x20
+//!         dw 0x1234
+//! ```
+//!
+//! Numbers are decimal, `0x`/`$0x`/`$` hex or `0b` binary, and may be
+//! combined with labels and constants using `+` and `-` (no spaces).
+//! Registers are `V0`-`V15` or `VA`-`VF`. XO-CHIP's 16-bit index load is
+//! written `LD I, long ADDR`.
+
+use crate::chip8::START_ADDRESS;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Where a line came from, for error messages.
+#[derive(Debug, Clone)]
+struct Origin {
+    file: String,
+    line: usize,
+}
+
+impl Origin {
+    fn error(&self, msg: impl std::fmt::Display) -> String {
+        format!("{}:{}: {msg}", self.file, self.line)
+    }
+}
+
+#[derive(Debug)]
+enum Item {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<String>,
+    },
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+}
+
+#[derive(Debug)]
+struct Statement {
+    origin: Origin,
+    addr: usize,
+    item: Item,
+}
+
+#[derive(Debug, Default)]
+struct Assembler {
+    statements: Vec<Statement>,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, (String, Origin)>,
+    addr: usize,
+}
+
+/// Assembles source text into a ROM image to be loaded at 0x200. Includes
+/// are resolved relative to the current directory.
+pub fn assemble(src: &str) -> Result<Vec<u8>, String> {
+    let mut asm = Assembler {
+        addr: START_ADDRESS,
+        ..Default::default()
+    };
+    asm.collect(src, "<input>", Path::new(""), 0)?;
+    asm.emit()
+}
+
+/// Assembles the file at `path`.
+pub fn assemble_file(path: impl AsRef<Path>) -> Result<Vec<u8>, String> {
+    let path = path.as_ref();
+    let src = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut asm = Assembler {
+        addr: START_ADDRESS,
+        ..Default::default()
+    };
+    asm.collect(&src, &path.display().to_string(), &base_dir(path), 0)?;
+    asm.emit()
+}
+
+fn base_dir(path: &Path) -> PathBuf {
+    path.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+impl Assembler {
+    /// First pass: lays out every statement and records label addresses.
+    fn collect(&mut self, src: &str, file: &str, dir: &Path, depth: usize) -> Result<(), String> {
+        for (i, line) in src.lines().enumerate() {
+            let origin = Origin {
+                file: file.to_string(),
+                line: i + 1,
+            };
+            let mut line = line.split(';').next().unwrap_or("").trim();
+
+            while let Some((label, rest)) = line.split_once(':')
+                && is_identifier(label.trim())
+            {
+                let label = label.trim();
+                if self.labels.insert(label.to_string(), self.addr).is_some()
+                    || self.constants.contains_key(label)
+                {
+                    return Err(origin.error(format!("`{label}` is already defined")));
+                }
+                line = rest.trim();
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            let (head, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+            if let Some(value) = rest.strip_prefix('=') {
+                if !is_identifier(head) {
+                    return Err(origin.error(format!("invalid constant name `{head}`")));
+                }
+                if self.labels.contains_key(head)
+                    || self
+                        .constants
+                        .insert(head.to_string(), (value.trim().to_string(), origin.clone()))
+                        .is_some()
+                {
+                    return Err(origin.error(format!("`{head}` is already defined")));
+                }
+                continue;
+            }
+
+            let operands: Vec<String> = rest
+                .replace(',', " ")
+                .split_whitespace()
+                .map(str::to_string)
+                .collect();
+            let (item, len) = match head.to_ascii_lowercase().as_str() {
+                "include" => {
+                    let name = rest.trim_matches('"');
+                    if name.is_empty() {
+                        return Err(origin.error("include needs a file name"));
+                    }
+                    if depth == MAX_INCLUDE_DEPTH {
+                        return Err(origin.error("includes nested too deeply"));
+                    }
+                    let path = dir.join(name);
+                    let src = fs::read_to_string(&path)
+                        .map_err(|e| origin.error(format!("{}: {e}", path.display())))?;
+                    self.collect(
+                        &src,
+                        &path.display().to_string(),
+                        &base_dir(&path),
+                        depth + 1,
+                    )?;
+                    continue;
+                }
+                "db" => (Item::Bytes(operands.clone()), operands.len()),
+                "dw" => (Item::Words(operands.clone()), operands.len() * 2),
+                _ => {
+                    let long = operands
+                        .get(1)
+                        .is_some_and(|op| op.eq_ignore_ascii_case("long"));
+                    let item = Item::Instruction {
+                        mnemonic: head.to_ascii_uppercase(),
+                        operands,
+                    };
+                    (item, if long { 4 } else { 2 })
+                }
+            };
+            self.statements.push(Statement {
+                origin,
+                addr: self.addr,
+                item,
+            });
+            self.addr += len;
+        }
+        Ok(())
+    }
+
+    /// Second pass: resolves symbols and encodes every statement.
+    fn emit(&self) -> Result<Vec<u8>, String> {
+        let mut rom = Vec::new();
+        for statement in &self.statements {
+            debug_assert_eq!(statement.addr, START_ADDRESS + rom.len());
+            let origin = &statement.origin;
+            match &statement.item {
+                Item::Bytes(values) => {
+                    for value in values {
+                        rom.push(self.ranged(value, 0xFF, origin)? as u8);
+                    }
+                }
+                Item::Words(values) => {
+                    for value in values {
+                        let word = self.ranged(value, 0xFFFF, origin)? as u16;
+                        rom.extend_from_slice(&word.to_be_bytes());
+                    }
+                }
+                Item::Instruction { mnemonic, operands } => {
+                    let operands: Vec<&str> = operands.iter().map(String::as_str).collect();
+                    for word in self.encode(mnemonic, &operands, origin)? {
+                        rom.extend_from_slice(&word.to_be_bytes());
+                    }
+                }
+            }
+        }
+        Ok(rom)
+    }
+
+    fn value(&self, expr: &str, origin: &Origin, depth: usize) -> Result<i64, String> {
+        if expr.is_empty() {
+            return Err(origin.error("missing value"));
+        }
+        let mut total: i64 = 0;
+        let mut sign = 1;
+        let mut start = 0;
+        // Split on + and - between terms, keeping a leading sign
+        for (i, c) in expr.char_indices().chain([(expr.len(), '+')]) {
+            if (c == '+' || c == '-') && i > start {
+                let term = self.term(&expr[start..i], origin, depth)?;
+                total = term
+                    .checked_mul(sign)
+                    .and_then(|term| total.checked_add(term))
+                    .ok_or_else(|| origin.error(format!("value out of range: `{expr}`")))?;
+                start = i + 1;
+                sign = if c == '-' { -1 } else { 1 };
+            } else if (c == '+' || c == '-') && i == start && i < expr.len() {
+                if c == '-' {
+                    sign = -sign;
+                }
+                start = i + 1;
+            }
+        }
+        Ok(total)
+    }
+
+    fn term(&self, term: &str, origin: &Origin, depth: usize) -> Result<i64, String> {
+        let invalid = || origin.error(format!("invalid number `{term}`"));
+        let hex = term
+            .strip_prefix("$0x")
+            .or_else(|| term.strip_prefix("0x"))
+            .or_else(|| term.strip_prefix('$'));
+        if let Some(digits) = hex {
+            return i64::from_str_radix(digits, 16).map_err(|_| invalid());
+        }
+        if let Some(digits) = term.strip_prefix("0b") {
+            return i64::from_str_radix(digits, 2).map_err(|_| invalid());
+        }
+        if term.starts_with(|c: char| c.is_ascii_digit()) {
+            return term.parse().map_err(|_| invalid());
+        }
+        if let Some(&addr) = self.labels.get(term) {
+            return Ok(addr as i64);
+        }
+        if let Some((expr, defined)) = self.constants.get(term) {
+            if depth == MAX_INCLUDE_DEPTH {
+                return Err(origin.error(format!("`{term}` is defined in terms of itself")));
+            }
+            return self.value(expr, defined, depth + 1);
+        }
+        Err(origin.error(format!("undefined symbol `{term}`")))
+    }
+
+    /// Evaluates `expr` and checks it fits in `0..=max`.
+    fn ranged(&self, expr: &str, max: i64, origin: &Origin) -> Result<i64, String> {
+        let value = self.value(expr, origin, 0)?;
+        if !(0..=max).contains(&value) {
+            return Err(origin.error(format!("`{expr}` = {value} does not fit in 0x{max:X}")));
+        }
+        Ok(value)
+    }
+
+    fn encode(&self, mnemonic: &str, ops: &[&str], origin: &Origin) -> Result<Vec<u16>, String> {
+        let reg = |op: &str| {
+            register(op).ok_or_else(|| origin.error(format!("expected a register, got `{op}`")))
+        };
+        let nnn = |op: &str| self.ranged(op, 0xFFF, origin).map(|v| v as u16);
+        let nn = |op: &str| self.ranged(op, 0xFF, origin).map(|v| v as u16);
+        let n = |op: &str| self.ranged(op, 0xF, origin).map(|v| v as u16);
+        let upper: Vec<String> = ops.iter().map(|op| op.to_ascii_uppercase()).collect();
+        let upper: Vec<&str> = upper.iter().map(String::as_str).collect();
+
+        let word = match (mnemonic, &upper[..]) {
+            // Only 0N00 decodes as SYS; the rest of 0NNN is other instructions
+            ("SYS", [_]) => match nnn(ops[0])? {
+                addr if addr & 0xFF == 0 => addr,
+                addr => {
+                    return Err(origin.error(format!(
+                        "SYS address 0x{addr:03X} is not a multiple of 0x100"
+                    )));
+                }
+            },
+            ("CLS", []) => 0x00E0,
+            ("RET", []) => 0x00EE,
+            ("SCR", []) => 0x00FB,
+            ("SCL", []) => 0x00FC,
+            ("EXIT", []) => 0x00FD,
+            ("LOW", []) => 0x00FE,
+            ("HIGH", []) => 0x00FF,
+            ("AUDIO", []) => 0xF002,
+            ("SCD", [_]) => 0x00C0 | n(ops[0])?,
+            ("SCU", [_]) => 0x00D0 | n(ops[0])?,
+            ("JMP" | "JP", ["V0", _]) => 0xB000 | nnn(ops[1])?,
+            ("JMP" | "JP", [_]) => 0x1000 | nnn(ops[0])?,
+            ("CALL", [_]) => 0x2000 | nnn(ops[0])?,
+            ("SE", [x, y]) if register(y).is_some() => 0x5000 | reg(x)? << 8 | reg(y)? << 4,
+            ("SE", [x, _]) => 0x3000 | reg(x)? << 8 | nn(ops[1])?,
+            ("SNE", [x, y]) if register(y).is_some() => 0x9000 | reg(x)? << 8 | reg(y)? << 4,
+            ("SNE", [x, _]) => 0x4000 | reg(x)? << 8 | nn(ops[1])?,
+            ("LD", ["I", "LONG", _]) => {
+                let addr = self.ranged(ops[2], 0xFFFF, origin)? as u16;
+                return Ok(vec![0xF000, addr]);
+            }
+            ("LD", ["I", _]) => 0xA000 | nnn(ops[1])?,
+            ("LD", ["DT", x]) => 0xF015 | reg(x)? << 8,
+            ("LD", ["ST", x]) => 0xF018 | reg(x)? << 8,
+            ("LD", ["F", x]) => 0xF029 | reg(x)? << 8,
+            ("LD", ["HF", x]) => 0xF030 | reg(x)? << 8,
+            ("LD", ["B", x]) => 0xF033 | reg(x)? << 8,
+            ("LD", ["R", x]) => 0xF075 | reg(x)? << 8,
+            ("LD", ["[I]", x]) => match x.split_once('-') {
+                Some((x, y)) => 0x5002 | reg(x)? << 8 | reg(y)? << 4,
+                None => 0xF055 | reg(x)? << 8,
+            },
+            ("LD", [x, "[I]"]) => match x.split_once('-') {
+                Some((x, y)) => 0x5003 | reg(x)? << 8 | reg(y)? << 4,
+                None => 0xF065 | reg(x)? << 8,
+            },
+            ("LD", [x, "DT"]) => 0xF007 | reg(x)? << 8,
+            ("LD", [x, "K"]) => 0xF00A | reg(x)? << 8,
+            ("LD", [x, "R"]) => 0xF085 | reg(x)? << 8,
+            ("LD", [x, y]) if register(y).is_some() => 0x8000 | reg(x)? << 8 | reg(y)? << 4,
+            ("LD", [x, _]) => 0x6000 | reg(x)? << 8 | nn(ops[1])?,
+            ("ADD", ["I", x]) => 0xF01E | reg(x)? << 8,
+            ("ADD", [x, y]) if register(y).is_some() => 0x8004 | reg(x)? << 8 | reg(y)? << 4,
+            ("ADD", [x, _]) => 0x7000 | reg(x)? << 8 | nn(ops[1])?,
+            ("OR" | "AND" | "XOR" | "SUB" | "SHR" | "SUBN" | "SHL", [x, rest @ ..])
+                if rest.len() <= 1 =>
+            {
+                let op = match mnemonic {
+                    "OR" => 0x1,
+                    "AND" => 0x2,
+                    "XOR" => 0x3,
+                    "SUB" => 0x5,
+                    "SHR" => 0x6,
+                    "SUBN" => 0x7,
+                    _ => 0xE,
+                };
+                // `SHR Vx` shifts Vx in place
+                let y = rest.first().copied().unwrap_or(x);
+                0x8000 | reg(x)? << 8 | reg(y)? << 4 | op
+            }
+            ("RND", [x, _]) => 0xC000 | reg(x)? << 8 | nn(ops[1])?,
+            ("DRW", [x, y, _]) => 0xD000 | reg(x)? << 8 | reg(y)? << 4 | n(ops[2])?,
+            ("SKP", [x]) => 0xE09E | reg(x)? << 8,
+            ("SKNP", [x]) => 0xE0A1 | reg(x)? << 8,
+            ("PLANE", [_]) => 0xF001 | n(ops[0])? << 8,
+            ("PITCH", [x]) => 0xF03A | reg(x)? << 8,
+            _ => {
+                return Err(origin.error(format!(
+                    "unknown instruction `{mnemonic} {}`",
+                    ops.join(", ")
+                )));
+            }
+        };
+        Ok(vec![word])
+    }
+}
+
+/// Parses `V0`-`V15` (as instructions display them) or `VA`-`VF`.
+fn register(op: &str) -> Option<u16> {
+    let digits = op.strip_prefix(['V', 'v'])?;
+    let reg = match digits.parse::<u16>() {
+        Ok(reg) => reg,
+        Err(_) if digits.len() == 1 => u16::from_str_radix(digits, 16).ok()?,
+        Err(_) => return None,
+    };
+    (reg < 16).then_some(reg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip8;
+    use crate::Instruction;
+
+    #[test]
+    fn assemble_test() {
+        let src = "
+            ; draw a digit and halt
+            DIGIT = 7
+            start:  LD V0, 0x001
+                    LD V1, DIGIT+1
+                    LD F, V1
+                    DRW V0, V0, $5
+                    CALL $0x20C
+            halt:   JMP halt
+                    RET
+                    SE V1 V2
+                    LD V10, VF
+            data:   db 0x80, 0b1, DIGIT
+                    dw 0x1234, data
+                    LD I, long data
+        ";
+        let rom = assemble(src).expect("should assemble");
+        assert_eq!(
+            rom,
+            [
+                0x60, 0x01, 0x61, 0x08, 0xF1, 0x29, 0xD0, 0x05, 0x22, 0x0C, 0x12, 0x0A, 0x00, 0xEE,
+                0x51, 0x20, 0x8A, 0xF0, 0x80, 0x01, 0x07, 0x12, 0x34, 0x02, 0x12, 0xF0, 0x00, 0x02,
+                0x12,
+            ]
+        );
+
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&rom).unwrap();
+        for _ in 0..6 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.pc(), 0x20A);
+        assert_eq!(chip8.sp(), 0);
+    }
+
+    #[test]
+    fn mnemonics_round_trip_test() {
+        // Every instruction's mnemonic must assemble back to the same
+        // opcode; the long load is tested above since it takes an operand
+        for op in 0..=0xFFFFu16 {
+            let Ok(instruction) = Instruction::decode(op) else {
+                continue;
+            };
+            if instruction == Instruction::LoadLong {
+                continue;
+            }
+            let text = instruction.to_string();
+            let rom = assemble(&text).unwrap_or_else(|e| panic!("{text}: {e}"));
+            assert_eq!(rom, op.to_be_bytes(), "{text}");
+        }
+    }
+
+    #[test]
+    fn errors_test() {
+        let err = |src: &str| assemble(src).unwrap_err();
+        assert_eq!(
+            err("CLS\nJMP nowhere"),
+            "<input>:2: undefined symbol `nowhere`"
+        );
+        assert_eq!(
+            err("LD V0, 256"),
+            "<input>:1: `256` = 256 does not fit in 0xFF"
+        );
+        assert_eq!(
+            err("LD V16, 1"),
+            "<input>:1: expected a register, got `V16`"
+        );
+        assert_eq!(err("a:\na: CLS"), "<input>:2: `a` is already defined");
+        assert_eq!(
+            err("\n\nFROB V1"),
+            "<input>:3: unknown instruction `FROB V1`"
+        );
+        assert_eq!(
+            err("A = B\nB = A\nJMP A"),
+            "<input>:2: `A` is defined in terms of itself"
+        );
+        assert_eq!(
+            err("SYS 0x123"),
+            "<input>:1: SYS address 0x123 is not a multiple of 0x100"
+        );
+        assert_eq!(
+            err("BIG = 0x7FFFFFFFFFFFFFFF\nLD V0, BIG+1"),
+            "<input>:2: value out of range: `BIG+1`"
+        );
+        assert!(err("include \"missing.asm\"").starts_with("<input>:1: missing.asm:"));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::chip8::TIMER_HZ;
+
+/// User-facing buzzer settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioSettings {
+    /// Tone frequency in Hz.
+    pub frequency: f32,
+    /// Output volume from 0.0 to 1.0.
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            frequency: 440.0,
+            volume: 0.25,
+            muted: false,
+        }
+    }
+}
+
+/// Where the buzzer goes. Frontends call [`AudioSink::update`] once per
+/// frame with [`crate::Chip8::sound_active`].
+pub trait AudioSink {
+    fn update(&mut self, playing: bool);
+
+    /// Switches from the plain tone to an XO-CHIP 128-bit sample pattern
+    /// played back at `rate` bits per second.
+    fn set_pattern(&mut self, _pattern: [u8; 16], _rate: f32) {}
+}
+
+/// Tone generator shared by every backend so they all sound alike: a square
+/// wave at the configured frequency, or an XO-CHIP pattern once one is set.
+#[derive(Debug, Clone)]
+pub struct SquareWave {
+    settings: AudioSettings,
+    sample_rate: u32,
+    phase: f32,
+    phase_inc: f32,
+    pattern: Option<[u8; 16]>,
+    playing: bool,
+}
+
+impl SquareWave {
+    pub fn new(settings: AudioSettings, sample_rate: u32) -> SquareWave {
+        SquareWave {
+            settings,
+            sample_rate,
+            phase: 0.0,
+            phase_inc: settings.frequency / sample_rate as f32,
+            pattern: None,
+            playing: false,
+        }
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+
+    pub fn set_pattern(&mut self, pattern: [u8; 16], rate: f32) {
+        self.pattern = Some(pattern);
+        // One phase cycle walks the whole 128-bit pattern
+        self.phase_inc = rate / 128.0 / self.sample_rate as f32;
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.settings.muted = muted;
+    }
+
+    pub fn settings(&self) -> &AudioSettings {
+        &self.settings
+    }
+
+    /// Fills `out` with the next samples, silence when not playing or muted.
+    pub fn fill(&mut self, out: &mut [f32]) {
+        let volume = if self.playing && !self.settings.muted {
+            self.settings.volume
+        } else {
+            0.0
+        };
+        for sample in out.iter_mut() {
+            let high = match &self.pattern {
+                Some(pattern) => {
+                    let bit = (self.phase * 128.0) as usize % 128;
+                    pattern[bit / 8] & (0x80 >> (bit % 8)) != 0
+                }
+                None => self.phase < 0.5,
+            };
+            *sample = if high { volume } else { -volume };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
+
+/// Headless sink that renders one frame worth of samples per update into
+/// memory, for tests and offline runs.
+#[derive(Debug, Clone)]
+pub struct CaptureSink {
+    wave: SquareWave,
+    samples_per_frame: usize,
+    samples: Vec<f32>,
+}
+
+impl CaptureSink {
+    pub fn new(settings: AudioSettings, sample_rate: u32) -> CaptureSink {
+        CaptureSink {
+            wave: SquareWave::new(settings, sample_rate),
+            samples_per_frame: (sample_rate / TIMER_HZ) as usize,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+}
+
+impl AudioSink for CaptureSink {
+    fn update(&mut self, playing: bool) {
+        self.wave.set_playing(playing);
+        let start = self.samples.len();
+        self.samples.resize(start + self.samples_per_frame, 0.0);
+        self.wave.fill(&mut self.samples[start..]);
+    }
+
+    fn set_pattern(&mut self, pattern: [u8; 16], rate: f32) {
+        self.wave.set_pattern(pattern, rate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_sink_test() {
+        let settings = AudioSettings {
+            frequency: 1000.0,
+            volume: 0.5,
+            muted: false,
+        };
+        let mut sink = CaptureSink::new(settings, 48000);
+        sink.update(true);
+        sink.update(false);
+        assert_eq!(sink.samples().len(), 1600);
+        // 48 samples per period: 24 high, 24 low
+        assert!(sink.samples()[..24].iter().all(|&s| s == 0.5));
+        assert!(sink.samples()[24..48].iter().all(|&s| s == -0.5));
+        assert!(sink.samples()[800..].iter().all(|&s| s == 0.0));
+
+        let mut muted = CaptureSink::new(
+            AudioSettings {
+                muted: true,
+                ..settings
+            },
+            48000,
+        );
+        muted.update(true);
+        assert!(muted.samples().iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn pattern_test() {
+        let mut sink = CaptureSink::new(AudioSettings::default(), 4000);
+        let mut pattern = [0u8; 16];
+        pattern[0] = 0xF0;
+        // One bit per sample
+        sink.set_pattern(pattern, 4000.0);
+        sink.update(true);
+        sink.update(true);
+        let v = AudioSettings::default().volume;
+        assert_eq!(sink.samples()[..6], [v, v, v, v, -v, -v]);
+        assert_eq!(sink.samples()[128..132], [v, v, v, v]);
+    }
+}
@@ -0,0 +1,44 @@
+use chip8_emu::cli::{self, OPTIONS_HELP};
+use std::{env, process};
+
+const USAGE: &str = "\
+usage: chip8-headless ROM [OPTIONS]
+
+Runs ROM without a window, as chip8-emu --headless does, and prints the
+final screen, state hash and registers.
+";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}\n{OPTIONS_HELP}");
+        return;
+    }
+
+    let config = cli::configure(&args)
+        .and_then(|mut config| {
+            config.headless = true;
+            config.check_headless()?;
+            Ok(config)
+        })
+        .unwrap_or_else(|err| {
+            eprintln!("Problem parsing arguments: {err}\nRun with --help for usage.");
+            process::exit(2);
+        });
+    let (mut chip8, playback) = cli::boot(&config).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(2);
+    });
+
+    let run = cli::run_headless(&config, &mut chip8, playback).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(2);
+    });
+    print!("{}", run.summary(&chip8));
+    if !run.failures.is_empty() {
+        for failure in &run.failures {
+            eprintln!("{failure}");
+        }
+        process::exit(1);
+    }
+}
@@ -0,0 +1,1292 @@
+use crate::error::ExecError;
+use crate::instruction::Instruction;
+use crate::octo;
+use crate::quirks::Quirks;
+use crate::random::{self, Random, SplitMix64};
+use crate::romdb::{RomDb, RomInfo};
+use crate::sha1;
+use crate::state::SaveState;
+use crate::trace::{TraceFilter, TraceRecord};
+use crate::watch::{Access, WatchHit, Watchpoint};
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+pub const DISPLAY_SIZE: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+pub const MEMORY_SIZE: usize = 4096;
+pub const XO_MEMORY_SIZE: usize = 0x10000;
+pub const START_ADDRESS: usize = 0x200;
+pub const FONT_ADDRESS: usize = 0x050;
+pub const BIG_FONT_ADDRESS: usize = FONT_ADDRESS + FONT.len();
+
+/// Rate at which the delay and sound timers count down, and at which
+/// frontends are expected to present frames.
+pub const TIMER_HZ: u32 = 60;
+
+pub const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// SUPER-CHIP 8x10 digits for FX30. The original only had 0-9; A-F follow
+/// Octo so XO-CHIP programs can use them too.
+pub const BIG_FONT: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// Which instruction set the machine decodes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// The original 64x32 CHIP-8.
+    #[default]
+    Chip8,
+    /// SUPER-CHIP 1.1: 128x64 hires mode, scrolling, big font, RPL flags.
+    SuperChip,
+    /// XO-CHIP: SUPER-CHIP plus 64 KiB memory, two bitplanes and an audio
+    /// pattern buffer.
+    XoChip,
+}
+
+impl Mode {
+    /// Picks the machine mode for a platform/preset name such as `"schip"`.
+    pub fn from_name(name: &str) -> Option<Mode> {
+        match name.to_ascii_lowercase().as_str() {
+            "default" | "vip" | "chip8" | "chip-8" | "chip48" | "chip-48" => Some(Mode::Chip8),
+            "schip" | "schip11" | "superchip" => Some(Mode::SuperChip),
+            "xochip" | "xo-chip" => Some(Mode::XoChip),
+            _ => None,
+        }
+    }
+
+    pub fn memory_size(self) -> usize {
+        match self {
+            Mode::XoChip => XO_MEMORY_SIZE,
+            _ => MEMORY_SIZE,
+        }
+    }
+}
+
+/// The CHIP-8 virtual machine. Owns memory, registers, timers, keypad and
+/// framebuffer; has no knowledge of how it is displayed or fed input.
+#[derive(Debug, Clone)]
+pub struct Chip8 {
+    registers: [u8; 16],
+    memory: Vec<u8>,
+    index: u16,
+    pc: usize,
+    stack: [u16; 16],
+    sp: usize,
+    delay_timer: u8,
+    sound_timer: u8,
+    keypad: [u8; 16],
+    /// One entry per pixel holding a bitmask of the planes lit there; only
+    /// XO-CHIP ever sets anything but plane 1.
+    video: Vec<u32>,
+    width: usize,
+    height: usize,
+    /// XO-CHIP planes affected by drawing, clearing and scrolling (FN01).
+    planes: u8,
+    /// XO-CHIP 1-bit, 128-sample audio pattern (F002), if one was loaded.
+    audio_pattern: Option<[u8; 16]>,
+    /// XO-CHIP playback pitch (FX3A); 64 is 4000 Hz.
+    pitch: u8,
+    /// SUPER-CHIP persistent "RPL user flags" (FX75/FX85).
+    flags: [u8; 16],
+    mode: Mode,
+    exited: bool,
+    quirks: Quirks,
+    /// Cleared by a draw when `display_wait` is on, set again at vblank.
+    vblank: bool,
+    /// Address and opcode of the instruction being executed.
+    current: (usize, u16),
+    watchpoints: Vec<Watchpoint>,
+    watch_hits: Vec<WatchHit>,
+    /// Instructions executed so far.
+    cycles: u64,
+    /// Instructions to trace, or `None` when tracing is off.
+    trace: Option<TraceFilter>,
+    trace_records: Vec<TraceRecord>,
+    /// Seed `rng` was last reset with, so runs can be replayed.
+    seed: u64,
+    rng: Box<dyn Random>,
+    /// SHA-1 of the file `load_rom` last loaded.
+    rom_sha1: Option<String>,
+    /// The ROM database's entry for it.
+    rom_info: Option<RomInfo>,
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chip8 {
+    pub fn new() -> Chip8 {
+        Chip8::with_quirks(Quirks::default())
+    }
+
+    pub fn with_quirks(quirks: Quirks) -> Chip8 {
+        Chip8::with_mode(Mode::Chip8, quirks)
+    }
+
+    pub fn with_mode(mode: Mode, quirks: Quirks) -> Chip8 {
+        let mut chip8 = Chip8 {
+            registers: [0u8; 16],
+            memory: vec![0u8; mode.memory_size()],
+            index: 0,
+            pc: START_ADDRESS,
+            stack: [0; 16],
+            sp: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            keypad: [0; 16],
+            video: vec![0; DISPLAY_SIZE],
+            width: DISPLAY_WIDTH,
+            height: DISPLAY_HEIGHT,
+            planes: 1,
+            audio_pattern: None,
+            pitch: 64,
+            flags: [0; 16],
+            mode,
+            exited: false,
+            quirks,
+            vblank: true,
+            current: (START_ADDRESS, 0),
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
+            cycles: 0,
+            trace: None,
+            trace_records: Vec::new(),
+            seed: 0,
+            rng: Box::new(SplitMix64::new(0)),
+            rom_sha1: None,
+            rom_info: None,
+        };
+        chip8.load_font();
+        chip8
+    }
+
+    fn fetch(&mut self) -> Result<u16, ExecError> {
+        if self.pc + 1 >= self.memory.len() {
+            return Err(ExecError::PcOutOfRange { pc: self.pc });
+        }
+        let msb = self.memory[self.pc];
+        let lsb = self.memory[self.pc + 1];
+        self.pc += 2;
+        // or the two bytes to make the instr
+        Ok(((msb as u16) << 8) | (lsb as u16))
+    }
+
+    /// Reads a data byte on behalf of the current instruction. The caller
+    /// has already bounds-checked `addr`.
+    fn read(&mut self, addr: usize) -> u8 {
+        let value = self.memory[addr];
+        if !self.watchpoints.is_empty() {
+            self.watch(addr, Access::Read, value);
+        }
+        value
+    }
+
+    /// Writes a data byte on behalf of the current instruction.
+    fn write(&mut self, addr: usize, value: u8) {
+        if !self.watchpoints.is_empty() {
+            self.watch(addr, Access::Write, value);
+        }
+        self.memory[addr] = value;
+    }
+
+    fn watch(&mut self, addr: usize, access: Access, value: u8) {
+        let (pc, opcode) = self.current;
+        for (watchpoint, wp) in self.watchpoints.iter().enumerate() {
+            if wp.matches(addr, access) {
+                self.watch_hits.push(WatchHit {
+                    watchpoint,
+                    access,
+                    addr,
+                    value,
+                    pc,
+                    opcode,
+                });
+            }
+        }
+    }
+
+    /// The opcode at PC, i.e. the next one `cycle` will execute.
+    pub fn current_opcode(&self) -> Option<u16> {
+        let bytes = self.memory.get(self.pc..self.pc + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Checks that `len` bytes starting at I are addressable and returns I.
+    fn index_range(&self, len: usize) -> Result<usize, ExecError> {
+        let start = self.index as usize;
+        if start + len > self.memory.len() {
+            return Err(ExecError::MemoryOutOfBounds {
+                addr: self.current.0 as u16,
+                index: start,
+            });
+        }
+        Ok(start)
+    }
+
+    /// Skips the next instruction. XO-CHIP's F000 NNNN is four bytes long
+    /// and is skipped whole.
+    fn skip(&mut self) {
+        let long = self.mode == Mode::XoChip
+            && self.memory.get(self.pc..self.pc + 2) == Some(&[0xF0, 0x00][..]);
+        self.pc += if long { 4 } else { 2 };
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> Result<(), ExecError> {
+        use Instruction::*;
+        let addr = self.current.0 as u16;
+        let reg = |r: u8| r as usize;
+        match instruction {
+            Sys(_) => {}
+            ScrollDown(n) => self.scroll(0, n as isize),
+            ScrollUp(n) => self.scroll(0, -(n as isize)),
+            Clear => {
+                let planes = self.planes as u32;
+                self.video.iter_mut().for_each(|p| *p &= !planes);
+            }
+            Return => {
+                if self.sp == 0 {
+                    return Err(ExecError::StackUnderflow { addr });
+                }
+                self.sp -= 1;
+                self.pc = self.stack[self.sp] as usize;
+            }
+            ScrollRight => self.scroll(4, 0),
+            ScrollLeft => self.scroll(-4, 0),
+            Exit => self.exited = true,
+            Lores => self.set_resolution(DISPLAY_WIDTH, DISPLAY_HEIGHT),
+            Hires => self.set_resolution(HIRES_WIDTH, HIRES_HEIGHT),
+            Jump(nnn) => self.pc = nnn as usize,
+            Call(nnn) => {
+                if self.sp == self.stack.len() {
+                    return Err(ExecError::StackOverflow { addr });
+                }
+                self.stack[self.sp] = self.pc as u16;
+                self.sp += 1;
+                self.pc = nnn as usize;
+            }
+            SkipEqImm { x, nn } => {
+                if self.registers[reg(x)] == nn {
+                    self.skip();
+                }
+            }
+            SkipNeImm { x, nn } => {
+                if self.registers[reg(x)] != nn {
+                    self.skip();
+                }
+            }
+            SkipEq { x, y } => {
+                if self.registers[reg(x)] == self.registers[reg(y)] {
+                    self.skip();
+                }
+            }
+            StoreRange { x, y } => {
+                let regs = register_range(reg(x), reg(y));
+                let i = self.index_range(regs.len())?;
+                for (offset, &r) in regs.iter().enumerate() {
+                    self.write(i + offset, self.registers[r]);
+                }
+            }
+            LoadRange { x, y } => {
+                let regs = register_range(reg(x), reg(y));
+                let i = self.index_range(regs.len())?;
+                for (offset, &r) in regs.iter().enumerate() {
+                    self.registers[r] = self.read(i + offset);
+                }
+            }
+            LoadImm { x, nn } => self.registers[reg(x)] = nn,
+            AddImm { x, nn } => {
+                self.registers[reg(x)] = self.registers[reg(x)].wrapping_add(nn);
+            }
+            Move { x, y } => self.registers[reg(x)] = self.registers[reg(y)],
+            Or { x, y } | And { x, y } | Xor { x, y } => {
+                let vy = self.registers[reg(y)];
+                let vx = &mut self.registers[reg(x)];
+                match instruction {
+                    Or { .. } => *vx |= vy,
+                    And { .. } => *vx &= vy,
+                    _ => *vx ^= vy,
+                }
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
+            }
+            Add { x, y } => {
+                let (result, overflow) =
+                    self.registers[reg(x)].overflowing_add(self.registers[reg(y)]);
+                self.registers[reg(x)] = result;
+                self.registers[0xF] = overflow as u8;
+            }
+            Sub { x, y } => {
+                let (vx, vy) = (self.registers[reg(x)], self.registers[reg(y)]);
+                // VF is the inverted borrow, written last so it wins when X
+                // is F
+                self.registers[reg(x)] = vx.wrapping_sub(vy);
+                self.registers[0xF] = (vx >= vy) as u8;
+            }
+            SubN { x, y } => {
+                let (vx, vy) = (self.registers[reg(x)], self.registers[reg(y)]);
+                self.registers[reg(x)] = vy.wrapping_sub(vx);
+                self.registers[0xF] = (vy >= vx) as u8;
+            }
+            ShiftRight { x, y } => {
+                let src = if self.quirks.shift_uses_vy { y } else { x };
+                let v = self.registers[reg(src)];
+                self.registers[reg(x)] = v >> 1;
+                self.registers[0xF] = v & 0x01;
+            }
+            ShiftLeft { x, y } => {
+                let src = if self.quirks.shift_uses_vy { y } else { x };
+                let v = self.registers[reg(src)];
+                self.registers[reg(x)] = v << 1;
+                self.registers[0xF] = v >> 7;
+            }
+            SkipNe { x, y } => {
+                if self.registers[reg(x)] != self.registers[reg(y)] {
+                    self.skip();
+                }
+            }
+            LoadI(nnn) => self.index = nnn,
+            JumpV0(nnn) => {
+                let offset = if self.quirks.jump_uses_vx {
+                    (nnn >> 8) as usize
+                } else {
+                    0
+                };
+                self.pc = nnn as usize + self.registers[offset] as usize;
+            }
+            Random { x, nn } => {
+                let entropy = self.rng.next_byte();
+                self.registers[reg(x)] = entropy & nn;
+            }
+            Draw { x, y, n } => {
+                if self.quirks.display_wait {
+                    if !self.vblank {
+                        // Retry this instruction after the next vblank
+                        self.pc -= 2;
+                        return Ok(());
+                    }
+                    self.vblank = false;
+                }
+                // DXY0 is a 16x16 sprite on SUPER-CHIP, a no-op otherwise
+                let (rows, wide) = match n {
+                    0 if self.mode != Mode::Chip8 => (16, true),
+                    _ => (n as usize, false),
+                };
+                self.draw_sprite(self.registers[reg(x)], self.registers[reg(y)], rows, wide)?;
+            }
+            SkipKey(x) => {
+                if self.keypad[self.registers[reg(x)] as usize & 0xF] == 1 {
+                    self.skip();
+                }
+            }
+            SkipNotKey(x) => {
+                if self.keypad[self.registers[reg(x)] as usize & 0xF] == 0 {
+                    self.skip();
+                }
+            }
+            LoadLong => self.index = self.fetch()?,
+            Plane(n) => self.planes = n & 0x3,
+            Audio => {
+                let i = self.index_range(16)?;
+                let mut pattern = [0u8; 16];
+                for (offset, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.read(i + offset);
+                }
+                self.audio_pattern = Some(pattern);
+            }
+            GetDelay(x) => self.registers[reg(x)] = self.delay_timer,
+            WaitKey(x) => match self.keypad.iter().position(|&key| key == 1) {
+                Some(key) => self.registers[reg(x)] = key as u8,
+                None => self.pc -= 2,
+            },
+            SetDelay(x) => self.delay_timer = self.registers[reg(x)],
+            SetSound(x) => self.sound_timer = self.registers[reg(x)],
+            AddI(x) => self.index = self.index.wrapping_add(self.registers[reg(x)] as u16),
+            Font(x) => {
+                self.index = FONT_ADDRESS as u16 + (self.registers[reg(x)] & 0xF) as u16 * 5;
+            }
+            BigFont(x) => {
+                self.index = BIG_FONT_ADDRESS as u16 + (self.registers[reg(x)] & 0xF) as u16 * 10;
+            }
+            Pitch(x) => self.pitch = self.registers[reg(x)],
+            Bcd(x) => {
+                let vx = self.registers[reg(x)];
+                let i = self.index_range(3)?;
+                self.write(i, vx / 100);
+                self.write(i + 1, vx / 10 % 10);
+                self.write(i + 2, vx % 10);
+            }
+            Store(x) => {
+                let x = reg(x);
+                let i = self.index_range(x + 1)?;
+                for r in 0..=x {
+                    self.write(i + r, self.registers[r]);
+                }
+                if self.quirks.load_store_increments_i {
+                    self.index = self.index.wrapping_add(x as u16 + 1);
+                }
+            }
+            Load(x) => {
+                let x = reg(x);
+                let i = self.index_range(x + 1)?;
+                for r in 0..=x {
+                    self.registers[r] = self.read(i + r);
+                }
+                if self.quirks.load_store_increments_i {
+                    self.index = self.index.wrapping_add(x as u16 + 1);
+                }
+            }
+            SaveFlags(x) => {
+                let x = reg(x);
+                self.flags[..=x].copy_from_slice(&self.registers[..=x]);
+            }
+            LoadFlags(x) => {
+                let x = reg(x);
+                self.registers[..=x].copy_from_slice(&self.flags[..=x]);
+            }
+        }
+        Ok(())
+    }
+
+    /// XORs a sprite from I onto the screen at (vx, vy). Wide sprites are
+    /// 16 pixels across, two bytes per row. With two planes selected the
+    /// second plane's sprite data directly follows the first's.
+    fn draw_sprite(&mut self, vx: u8, vy: u8, rows: usize, wide: bool) -> Result<(), ExecError> {
+        let cols = if wide { 16 } else { 8 };
+        let bytes_per_row = cols / 8;
+        let sprite_len = rows * bytes_per_row;
+        // The start position always wraps; the quirk decides what
+        // happens to the parts of the sprite that run off the edge.
+        let x_coord = vx as usize % self.width;
+        let y_coord = vy as usize % self.height;
+        let mut sprite = self.index_range(sprite_len * self.planes.count_ones() as usize)?;
+        self.registers[0xF] = 0;
+
+        for plane in [1u32, 2] {
+            if self.planes as u32 & plane == 0 {
+                continue;
+            }
+            for row in 0..rows {
+                let at = sprite + row * bytes_per_row;
+                let bits = if wide {
+                    u16::from_be_bytes([self.read(at), self.read(at + 1)])
+                } else {
+                    (self.read(at) as u16) << 8
+                };
+                let cy = y_coord + row;
+                if cy >= self.height && self.quirks.clip_sprites {
+                    break;
+                }
+                let cy = cy % self.height;
+                for col in 0..cols {
+                    let cx = x_coord + col;
+                    if cx >= self.width && self.quirks.clip_sprites {
+                        break;
+                    }
+                    let cx = cx % self.width;
+                    let sprite_pixel = bits & (0x8000 >> col);
+
+                    let screen_pixel_loc = (cy * self.width) + cx;
+                    let screen_pixel = self.video[screen_pixel_loc] & plane;
+                    if sprite_pixel > 0 {
+                        if screen_pixel > 0 {
+                            self.registers[0xF] = 1;
+                        }
+
+                        self.video[screen_pixel_loc] ^= plane;
+                    }
+                }
+            }
+            sprite += sprite_len;
+        }
+        Ok(())
+    }
+
+    /// Switches between lores and hires; the screen is cleared on change.
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.video = vec![0; width * height];
+    }
+
+    /// Shifts the selected planes by (dx, dy) pixels, filling with blank.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let planes = self.planes as u32;
+        let source = self.video.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (sx, sy) = (x as isize - dx, y as isize - dy);
+                let shifted = if (0..self.width as isize).contains(&sx)
+                    && (0..self.height as isize).contains(&sy)
+                {
+                    source[sy as usize * self.width + sx as usize]
+                } else {
+                    0
+                };
+                let pixel = &mut self.video[y * self.width + x];
+                *pixel = (*pixel & !planes) | (shifted & planes);
+            }
+        }
+    }
+
+    fn load_font(&mut self) {
+        // 050–09F
+        self.memory[FONT_ADDRESS..FONT_ADDRESS + FONT.len()].copy_from_slice(&FONT);
+        // 0A0–13F
+        self.memory[BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
+    }
+
+    /// Executes one instruction. On error the machine state is left as it
+    /// was at the point of the fault and the caller decides whether to stop.
+    /// Timers are not touched; see [`Chip8::tick_timers`].
+    pub fn cycle(&mut self) -> Result<(), ExecError> {
+        if self.exited {
+            return Ok(());
+        }
+        let pc = self.pc;
+        let instr = self.fetch()?;
+        self.current = (pc, instr);
+        let cycle = self.cycles;
+        self.cycles += 1;
+        let instruction = Instruction::decode(instr)
+            .ok()
+            .filter(|instruction| instruction.supported_in(self.mode))
+            .ok_or(ExecError::IllegalOpcode {
+                opcode: instr,
+                addr: pc as u16,
+            })?;
+        if !self.trace.as_ref().is_some_and(|f| f.matches(pc, instr)) {
+            return self.execute(instruction);
+        }
+
+        let before = self.registers;
+        let result = self.execute(instruction);
+        let changes = (0..16)
+            .filter(|&r| before[r] != self.registers[r])
+            .map(|r| (r as u8, before[r], self.registers[r]))
+            .collect();
+        self.trace_records.push(TraceRecord {
+            cycle,
+            pc,
+            opcode: instr,
+            instruction: instruction.to_string(),
+            changes,
+            index: self.index,
+            sp: self.sp,
+        });
+        result
+    }
+
+    /// Starts tracing the instructions `filter` matches, or stops tracing.
+    pub fn set_trace(&mut self, filter: Option<TraceFilter>) {
+        self.trace = filter;
+    }
+
+    /// Drains the trace records made since the last call.
+    pub fn take_trace(&mut self) -> Vec<TraceRecord> {
+        std::mem::take(&mut self.trace_records)
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Counts the delay and sound timers down by one and signals vblank.
+    /// Call this at [`TIMER_HZ`], independently of the instruction rate.
+    pub fn tick_timers(&mut self) {
+        self.vblank = true;
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
+    /// Runs one 60 Hz frame: `instructions_per_frame` instructions followed
+    /// by a timer tick.
+    pub fn run_frame(&mut self, instructions_per_frame: u32) -> Result<(), ExecError> {
+        for _ in 0..instructions_per_frame {
+            self.cycle()?;
+        }
+        self.tick_timers();
+        Ok(())
+    }
+
+    /// Loads a ROM image, or compiles and loads Octo source if the file
+    /// name ends in `.8o`.
+    pub fn load_rom(&mut self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        let mut file = File::open(file_path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let hash = sha1::hex_digest(&bytes);
+        self.rom_info = RomDb::bundled().lookup(&hash);
+        self.rom_sha1 = Some(hash);
+        if file_path.ends_with(".8o") {
+            let src = String::from_utf8(bytes)?;
+            bytes = octo::compile(&src).map_err(|err| format!("{file_path}:{err}"))?;
+        }
+        self.load_rom_bytes(&bytes)
+    }
+
+    /// SHA-1 of the file loaded by [`Chip8::load_rom`], as hex.
+    pub fn rom_sha1(&self) -> Option<&str> {
+        self.rom_sha1.as_deref()
+    }
+
+    /// What the bundled ROM database knows about the loaded file.
+    pub fn rom_info(&self) -> Option<&RomInfo> {
+        self.rom_info.as_ref()
+    }
+
+    /// Copies a ROM image into memory at the program start address.
+    pub fn load_rom_bytes(&mut self, rom: &[u8]) -> Result<(), Box<dyn Error>> {
+        let capacity = self.memory.len() - START_ADDRESS;
+        if rom.len() > capacity {
+            return Err(format!("ROM is {} bytes, max is {capacity}", rom.len()).into());
+        }
+        self.memory[START_ADDRESS..START_ADDRESS + rom.len()].copy_from_slice(rom);
+        Ok(())
+    }
+
+    /// Captures the machine state, everything but watchpoints and tracing.
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            mode: self.mode,
+            quirks: self.quirks,
+            registers: self.registers,
+            memory: self.memory.clone(),
+            index: self.index,
+            pc: self.pc,
+            stack: self.stack,
+            sp: self.sp,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            keypad: self.keypad,
+            video: self.video.clone(),
+            width: self.width,
+            height: self.height,
+            planes: self.planes,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            flags: self.flags,
+            exited: self.exited,
+            vblank: self.vblank,
+            cycles: self.cycles,
+            rng: self.rng.kind().to_string(),
+            seed: self.seed,
+            rng_state: self.rng.state(),
+        }
+    }
+
+    /// Puts the machine back into a saved state. Watchpoints and tracing
+    /// are left as they are.
+    pub fn load_state(&mut self, state: SaveState) {
+        self.mode = state.mode;
+        self.quirks = state.quirks;
+        self.registers = state.registers;
+        self.memory = state.memory;
+        self.index = state.index;
+        self.pc = state.pc;
+        self.stack = state.stack;
+        self.sp = state.sp;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.keypad = state.keypad;
+        self.video = state.video;
+        self.width = state.width;
+        self.height = state.height;
+        self.planes = state.planes;
+        self.audio_pattern = state.audio_pattern;
+        self.pitch = state.pitch;
+        self.flags = state.flags;
+        self.exited = state.exited;
+        self.vblank = state.vblank;
+        self.cycles = state.cycles;
+        if let Some(rng) = random::from_name(&state.rng, 0) {
+            self.rng = rng;
+        }
+        self.seed = state.seed;
+        self.rng.restore(state.rng_state);
+        self.current = (self.pc, 0);
+    }
+
+    /// Adds a watchpoint and returns its index.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.watchpoints.push(watchpoint);
+        self.watchpoints.len() - 1
+    }
+
+    pub fn remove_watchpoint(&mut self, n: usize) -> Option<Watchpoint> {
+        (n < self.watchpoints.len()).then(|| self.watchpoints.remove(n))
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Drains the watchpoint hits recorded since the last call.
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.watch_hits)
+    }
+
+    /// Restarts the CXNN random number sequence from `seed`. Machines start
+    /// out seeded with 0.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng.reseed(seed);
+    }
+
+    /// The name of the CXNN generator in use, e.g. `"splitmix"`.
+    pub fn rng_kind(&self) -> &'static str {
+        self.rng.kind()
+    }
+
+    /// Replaces the CXNN generator, reseeding it with the current seed.
+    pub fn set_random(&mut self, mut rng: Box<dyn Random>) {
+        rng.reseed(self.seed);
+        self.rng = rng;
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Marks a key (0x0-0xF) as pressed or released.
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        self.keypad[key as usize & 0xF] = pressed as u8;
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// The framebuffer, row-major, [`Chip8::display_width`] pixels per row.
+    pub fn video(&self) -> &[u32] {
+        &self.video
+    }
+
+    pub fn display_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn display_height(&self) -> usize {
+        self.height
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Bitmask of the planes selected for drawing (XO-CHIP FN01).
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// The XO-CHIP audio pattern, once a program has loaded one with F002.
+    pub fn audio_pattern(&self) -> Option<&[u8; 16]> {
+        self.audio_pattern.as_ref()
+    }
+
+    /// XO-CHIP pattern playback rate in Hz, derived from the pitch register.
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    /// True once a SUPER-CHIP program has executed 00FD (EXIT).
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn keypad(&self) -> &[u8; 16] {
+        &self.keypad
+    }
+
+    pub fn stack(&self) -> &[u16; 16] {
+        &self.stack
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Whether the buzzer should currently be sounding.
+    pub fn sound_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// Memory from `start` up to `end` as lines of 16 hex bytes.
+    pub fn memory_hexdump(&self, start: u16, end: u16) -> String {
+        let mut dump = String::new();
+        for (i, chunk) in self.memory[start as usize..end as usize]
+            .chunks(16)
+            .enumerate()
+        {
+            dump.push_str(&format!("0x{:03X}:", (start as usize) + (i * 16)));
+            for byte in chunk {
+                dump.push_str(&format!(" {:02X}", byte));
+            }
+            dump.push('\n');
+        }
+        dump
+    }
+}
+
+/// Registers VX..=VY for 5XY2/5XY3, which may also run backwards.
+fn register_range(x: usize, y: usize) -> Vec<usize> {
+    if x <= y {
+        (x..=y).collect()
+    } else {
+        (y..=x).rev().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_maze_rom_test() {
+        let fp = "maze.ch8".to_string();
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&fp).expect("should load the rom");
+        let dump = chip8.memory_hexdump(0x200, 0x238);
+        assert_eq!(dump.lines().count(), 4);
+        assert!(dump.starts_with("0x200: 60 00 61 00 A2 22"));
+        assert_eq!(chip8.memory[0x200], 0x60);
+        assert_eq!(chip8.memory[0x200], 0x60);
+        assert_eq!(chip8.memory[0x201], 0x00);
+        assert_eq!(chip8.memory[0x202], 0x61);
+        assert_eq!(chip8.memory[0x203], 0x00);
+        assert_eq!(chip8.memory[0x204], 0xA2);
+        assert_eq!(chip8.memory[0x205], 0x22);
+        assert_eq!(chip8.memory[0x210], 0x30);
+        assert_eq!(chip8.memory[0x220], 0x20);
+        assert_eq!(chip8.memory[0x221], 0x10);
+        assert_eq!(chip8.memory[0x230], 0x00);
+        assert_eq!(
+            chip8.rom_sha1(),
+            Some("8b70080adbac44513ec60005734a816372b845ec")
+        );
+        assert_eq!(chip8.rom_info().unwrap().title, "Maze");
+    }
+
+    #[test]
+    fn load_font_test() {
+        let mut chip8 = Chip8::new();
+        chip8.load_font();
+        assert_eq!(FONT, chip8.memory[0x050..0x09f + 1]);
+    }
+
+    #[test]
+    fn load_rom_bytes_test() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom_bytes(&[0x60, 0x2A])
+            .expect("should load the rom");
+        chip8.cycle().expect("should execute");
+        assert_eq!(chip8.registers()[0], 0x2A);
+        assert_eq!(chip8.pc(), START_ADDRESS + 2);
+
+        let too_big = vec![0u8; MEMORY_SIZE];
+        assert!(chip8.load_rom_bytes(&too_big).is_err());
+    }
+
+    #[test]
+    fn arithmetic_flags_test() {
+        // LD V0, 0xFF; LD VF, 0x01; ADD VF, V0; LD V1, 0x05; LD V2, 0x05; SUB V1, V2
+        let mut chip8 = Chip8::new();
+        let rom = [
+            0x60, 0xFF, 0x6F, 0x01, 0x8F, 0x04, 0x61, 0x05, 0x62, 0x05, 0x81, 0x25,
+        ];
+        chip8.load_rom_bytes(&rom).unwrap();
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        // The carry overwrites the sum when the destination is VF
+        assert_eq!(chip8.registers()[0xF], 1);
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        // Equal operands do not borrow
+        assert_eq!(chip8.registers()[1], 0);
+        assert_eq!(chip8.registers()[0xF], 1);
+    }
+
+    #[test]
+    fn random_test() {
+        // RND V0, 0xFF; RND V1, 0x0F; JP 0x200
+        let rom = [0xC0, 0xFF, 0xC1, 0x0F, 0x12, 0x00];
+        let run = |seed: u64| {
+            let mut chip8 = Chip8::new();
+            chip8.load_rom_bytes(&rom).unwrap();
+            chip8.set_seed(seed);
+            let mut values = Vec::new();
+            for _ in 0..300 {
+                chip8.run_frame(3).unwrap();
+                values.push(chip8.registers()[0]);
+                assert!(chip8.registers()[1] <= 0x0F);
+            }
+            values
+        };
+        assert_eq!(run(3), run(3));
+        assert_ne!(run(3), run(4));
+        assert!(run(0).contains(&0xFF));
+
+        // A save state carries on the same sequence
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&rom).unwrap();
+        chip8.set_seed(5);
+        chip8.run_frame(3).unwrap();
+        let state = chip8.save_state();
+        chip8.run_frame(3).unwrap();
+        let mut restored = Chip8::new();
+        restored.load_state(state);
+        restored.run_frame(3).unwrap();
+        assert_eq!(restored.registers(), chip8.registers());
+        assert_eq!(restored.seed(), 5);
+    }
+
+    #[test]
+    fn exec_errors_test() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x00, 0xEE]).unwrap();
+        assert_eq!(
+            chip8.cycle(),
+            Err(ExecError::StackUnderflow { addr: 0x200 })
+        );
+
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x80, 0x0F]).unwrap();
+        assert_eq!(
+            chip8.cycle(),
+            Err(ExecError::IllegalOpcode {
+                opcode: 0x800F,
+                addr: 0x200
+            })
+        );
+
+        // CALL 0x200 forever fills the stack
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x22, 0x00]).unwrap();
+        for _ in 0..16 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.cycle(), Err(ExecError::StackOverflow { addr: 0x200 }));
+
+        // LD I, 0xFFF; LD [I], V1
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0xAF, 0xFF, 0xF1, 0x55]).unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(
+            chip8.cycle(),
+            Err(ExecError::MemoryOutOfBounds {
+                addr: 0x202,
+                index: 0xFFF
+            })
+        );
+
+        // JP 0xFFF
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x1F, 0xFF]).unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.cycle(), Err(ExecError::PcOutOfRange { pc: 0xFFF }));
+
+        // F000 at the last address has no room for its NNNN
+        let mut chip8 = Chip8::with_mode(Mode::XoChip, Quirks::xochip());
+        chip8.memory[0xFFFE] = 0xF0;
+        chip8.pc = 0xFFFE;
+        assert_eq!(chip8.cycle(), Err(ExecError::PcOutOfRange { pc: 0x10000 }));
+    }
+
+    #[test]
+    fn quirks_test() {
+        // LD V1, 0x81; LD V2, 0x03; SHR V1, V2; SHL V2, V1
+        let rom = [0x61, 0x81, 0x62, 0x03, 0x81, 0x26, 0x82, 0x1E];
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&rom).unwrap();
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.registers[1], 0x40);
+        assert_eq!(chip8.registers[0xF], 1);
+
+        let mut chip8 = Chip8::with_quirks(Quirks::vip());
+        chip8.load_rom_bytes(&rom).unwrap();
+        for _ in 0..4 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.registers[1], 0x01);
+        assert_eq!(chip8.registers[2], 0x02);
+        assert_eq!(chip8.registers[0xF], 0);
+
+        // LD V0, 0x3E; LD V1, 0x00; LD I, 0x050; DRW V0, V1, 5 (font "0" at x=62)
+        let rom = [0x60, 0x3E, 0x61, 0x00, 0xA0, 0x50, 0xD0, 0x15];
+        let mut chip8 = Chip8::with_quirks(Quirks::vip());
+        chip8.load_rom_bytes(&rom).unwrap();
+        for _ in 0..4 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.video[63], 1);
+        assert_eq!(chip8.video[0], 0);
+
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&rom).unwrap();
+        for _ in 0..4 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.video[63], 1);
+        assert_eq!(chip8.video[0], 1);
+    }
+
+    #[test]
+    fn timers_test() {
+        // LD V0, 0x05; LD DT, V0; JP 0x204
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom_bytes(&[0x60, 0x05, 0xF0, 0x15, 0x12, 0x04])
+            .unwrap();
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        for _ in 0..100 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.delay_timer(), 5);
+
+        chip8.run_frame(10).unwrap();
+        chip8.run_frame(1000).unwrap();
+        assert_eq!(chip8.delay_timer(), 3);
+    }
+
+    #[test]
+    fn display_wait_test() {
+        // LD I, 0x050; DRW V0, V0, 1; JP 0x202
+        let mut chip8 = Chip8::with_quirks(Quirks::vip());
+        chip8
+            .load_rom_bytes(&[0xA0, 0x50, 0xD0, 0x01, 0x12, 0x02])
+            .unwrap();
+        chip8.run_frame(20).unwrap();
+        // Only the first draw of the frame went through
+        assert_eq!(chip8.video[0], 1);
+        chip8.run_frame(20).unwrap();
+        assert_eq!(chip8.video[0], 0);
+    }
+
+    #[test]
+    fn superchip_test() {
+        // HIGH; LD V0, 0x7E; LD I, 0x0A0; DRW V0, V1, 0; EXIT; JP 0x200
+        let rom = [
+            0x00, 0xFF, 0x60, 0x7E, 0xA0, 0xA0, 0xD0, 0x10, 0x00, 0xFD, 0x12, 0x00,
+        ];
+        let mut chip8 = Chip8::with_mode(Mode::SuperChip, Quirks::schip());
+        chip8.load_rom_bytes(&rom).unwrap();
+        chip8.run_frame(100).unwrap();
+        assert!(chip8.exited());
+        assert_eq!(chip8.pc(), 0x20A);
+        assert_eq!((chip8.display_width(), chip8.display_height()), (128, 64));
+        // Big "0" clipped to its first two columns at x=126
+        assert_eq!(chip8.video()[126], 1);
+        assert_eq!(chip8.video()[127], 1);
+        assert_eq!(chip8.video()[0], 0);
+
+        chip8.scroll(-4, 0);
+        assert_eq!(chip8.video()[122], 1);
+        assert_eq!(chip8.video()[126], 0);
+        chip8.scroll(0, 2);
+        assert_eq!(chip8.video()[122], 0);
+        assert_eq!(chip8.video()[2 * 128 + 122], 1);
+        chip8.scroll(4, 0);
+        assert_eq!(chip8.video()[2 * 128 + 126], 1);
+
+        // The extensions are illegal in plain CHIP-8 mode
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&rom).unwrap();
+        assert!(chip8.cycle().is_err());
+    }
+
+    #[test]
+    fn rpl_flags_test() {
+        // LD V0, 0x11; LD V1, 0x22; LD R, V1; LD V0, 0; LD V1, 0; LD V1, R
+        let rom = [
+            0x60, 0x11, 0x61, 0x22, 0xF1, 0x75, 0x60, 0x00, 0x61, 0x00, 0xF1, 0x85,
+        ];
+        let mut chip8 = Chip8::with_mode(Mode::SuperChip, Quirks::schip());
+        chip8.load_rom_bytes(&rom).unwrap();
+        for _ in 0..6 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.registers()[..2], [0x11, 0x22]);
+    }
+
+    #[test]
+    fn xochip_test() {
+        let mut rom = vec![
+            0xF0, 0x00, 0x80, 0x00, // LD I, 0x8000
+            0x60, 0x01, // LD V0, 0x01
+            0x30, 0x01, // SE V0, 0x01
+            0xF0, 0x00, 0x12, 0x34, // LD I, 0x1234 (skipped whole)
+            0x61, 0x02, // LD V1, 0x02
+            0x50, 0x12, // LD [I], V0-V1
+            0xF3, 0x01, // PLANE 3
+            0xA3, 0x00, // LD I, 0x300
+            0xD2, 0x21, // DRW V2, V2, 1
+            0xF2, 0x01, // PLANE 2
+            0x00, 0xC1, // SCD 1
+        ];
+        rom.resize(0x100, 0);
+        // Plane 1 then plane 2 sprite rows at 0x300
+        rom.extend_from_slice(&[0x80, 0xC0]);
+        let mut chip8 = Chip8::with_mode(Mode::XoChip, Quirks::xochip());
+        assert_eq!(chip8.memory().len(), 0x10000);
+        chip8.load_rom_bytes(&rom).unwrap();
+        for _ in 0..8 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.memory()[0x8000..0x8002], [0x01, 0x02]);
+        assert_eq!(chip8.video()[0], 0b11);
+        assert_eq!(chip8.video()[1], 0b10);
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        // Only plane 2 moved down
+        assert_eq!(chip8.video()[0], 0b01);
+        assert_eq!(chip8.video()[1], 0);
+        assert_eq!(chip8.video()[64], 0b10);
+        assert_eq!(chip8.video()[64 + 1], 0b10);
+
+        // LD I, 0x300; AUDIO; LD V0, 0x70; PITCH V0
+        let mut chip8 = Chip8::with_mode(Mode::XoChip, Quirks::xochip());
+        chip8
+            .load_rom_bytes(&[0xA3, 0x00, 0xF0, 0x02, 0x60, 0x70, 0xF0, 0x3A])
+            .unwrap();
+        for _ in 0..4 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.audio_pattern(), Some(&[0u8; 16]));
+        assert!((chip8.playback_rate() - 8000.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn index_wrap_test() {
+        // LD I, 0xFFF0; LD [I], V0-VF; LD V0-VF, [I]
+        let rom = [0xF0, 0x00, 0xFF, 0xF0, 0xFF, 0x55, 0xFF, 0x65];
+        let mut chip8 = Chip8::with_mode(Mode::XoChip, Quirks::xochip());
+        chip8.load_rom_bytes(&rom).unwrap();
+        chip8.registers[0xF] = 0x42;
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.memory()[0xFFFF], 0x42);
+        assert_eq!(chip8.index, 0);
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.index, 0x10);
+    }
+
+    #[test]
+    fn watchpoint_test() {
+        // LD V0, 0x7B; LD I, 0x300; LD B, V0; LD V2, [I]
+        let rom = [0x60, 0x7B, 0xA3, 0x00, 0xF0, 0x33, 0xF2, 0x65];
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&rom).unwrap();
+        chip8.add_watchpoint(Watchpoint {
+            range: 0x301..0x302,
+            on_read: false,
+            on_write: true,
+            log_only: false,
+        });
+        chip8.add_watchpoint(Watchpoint {
+            range: 0x300..0x310,
+            on_read: true,
+            on_write: false,
+            log_only: true,
+        });
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        let hits = chip8.take_watch_hits();
+        assert_eq!(
+            hits,
+            [WatchHit {
+                watchpoint: 0,
+                access: Access::Write,
+                addr: 0x301,
+                value: 2,
+                pc: 0x204,
+                opcode: 0xF033,
+            }]
+        );
+        chip8.cycle().unwrap();
+        let hits = chip8.take_watch_hits();
+        assert_eq!(hits.len(), 3);
+        assert!(hits.iter().all(|h| h.watchpoint == 1 && h.pc == 0x206));
+        assert!(chip8.take_watch_hits().is_empty());
+    }
+}
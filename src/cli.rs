@@ -0,0 +1,619 @@
+//! Command-line options shared by `chip8-emu` and `chip8-headless`, and the
+//! headless run both offer, so that a ROM boots and runs the same whichever
+//! one starts it.
+
+use crate::chip8::Chip8;
+use crate::headless::{self, KeyScript, RunLimit, RunReport};
+use crate::{
+    AudioSettings, Mode, Movie, Palette, Quirks, RomDb, SaveState, Settings, TraceFilter,
+    TraceFormat, TraceWriter, gamepad, movie, png, settings, sha1,
+};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::str::FromStr;
+
+/// Help for the options [`Config::build`] takes.
+pub const OPTIONS_HELP: &str = "\
+Options:
+  --scale N             window or PNG scale (default 2)
+  --ipf N               instructions per frame (default 10)
+  --mode MODE           chip8, schip or xochip, with that platform's
+                        quirks unless --quirks is given
+  --quirks PRESET       quirk preset (vip, chip48, schip, xochip, ...),
+                        then optional switches, e.g. schip,+vblank,-clip
+  --palette PALETTE     classic, green, amber, gameboy or high-contrast,
+                        or RRGGBB,RRGGBB[,RRGGBB[,RRGGBB]] for off, on and
+                        the XO-CHIP plane 2 and both-planes colours
+  --frequency HZ        buzzer pitch
+  --volume V            buzzer volume, 0 to 1
+  --mute[=false]        start muted, or not
+  --seed N              random number seed (random if not given, 0 for
+                        headless runs)
+  --headless            run without a window and print the final state
+                        (not with --record or --debug)
+  --frames N            frames to run headless (default 60)
+  --instructions N      instructions to run headless, instead of --frames
+  --keys FILE           key presses for a headless run
+  --png FILE            write the final screen of a headless run
+  --save-state FILE     write the final state of a headless run
+  --debug               start paused at the debugger prompt
+  --trace FILE          write an instruction trace
+  --trace-format FMT    text, csv or bin
+  --trace-range A-B     only trace addresses A to B
+  --trace-ops CLASSES   only trace these opcode classes, e.g. 8,D
+  --state FILE          start from a save state
+  --rewind SECONDS      seconds kept for rewinding (default 10, 0 disables)
+  --rewind-mem MIB      rewind memory cap (default 16)
+  --record FILE         record input to a movie
+  --play FILE           replay a movie and check its final state
+  --keymap FILE         keymap file (default keymap.txt)
+  --dead-zone N         analog stick dead zone, 0 to 32767
+  --expect HASH         fail a headless run unless the final state hash
+                        matches
+  -h, --help            show this help";
+
+/// Keymap file used when `--keymap` isn't given. It is only read if it
+/// exists, and is where keys bound in the window are saved.
+pub const DEFAULT_KEYMAP_PATH: &str = "keymap.txt";
+
+#[derive(Debug)]
+pub struct Config {
+    pub file_path: String,
+    pub video_scale_factor: u32,
+    pub instructions_per_frame: u32,
+    pub mode: Mode,
+    pub quirks: Quirks,
+    pub audio: AudioSettings,
+    /// Screen colours (`--palette`).
+    pub palette: Palette,
+    /// Run without a window for `frames` frames (`--headless`).
+    pub headless: bool,
+    pub frames: u64,
+    /// Run this many instructions instead of `frames` (`--instructions`).
+    pub instructions: Option<u64>,
+    /// Key presses for a headless run (`--keys`).
+    pub key_script_path: Option<String>,
+    /// Write the final screen of a headless run here (`--png`).
+    pub png_path: Option<String>,
+    /// Save the state a headless run ends in here (`--save-state`).
+    pub save_state_path: Option<String>,
+    /// Start paused with the debugger prompt on stdin.
+    pub debug: bool,
+    /// Write an instruction trace here (`--trace`).
+    pub trace_path: Option<String>,
+    pub trace_format: TraceFormat,
+    pub trace_filter: TraceFilter,
+    /// Resume from this save state instead of the ROM's start (`--state`).
+    pub state_path: Option<String>,
+    /// Seconds of play kept for rewinding (`--rewind`, 0 disables).
+    pub rewind_seconds: u32,
+    /// Memory cap for the rewind buffer in MiB (`--rewind-mem`).
+    pub rewind_mib: usize,
+    /// Record the keypad to an input movie (`--record`).
+    pub record_path: Option<String>,
+    /// Replay an input movie instead of reading the keyboard (`--play`).
+    pub play_path: Option<String>,
+    /// CXNN seed (`--seed`); a random one when not given.
+    pub seed: Option<u64>,
+    /// Keymap file with global and per-ROM bindings (`--keymap`).
+    pub keymap_path: String,
+    /// How far a stick must move to press its direction (`--dead-zone`,
+    /// out of 32767).
+    pub dead_zone: i16,
+    /// State hash `test` expects to finish with (`--expect`).
+    pub expect_hash: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            file_path: String::new(),
+            video_scale_factor: 2,
+            instructions_per_frame: 10,
+            mode: Mode::default(),
+            quirks: Quirks::default(),
+            audio: AudioSettings::default(),
+            palette: Palette::default(),
+            headless: false,
+            frames: 60,
+            instructions: None,
+            key_script_path: None,
+            png_path: None,
+            save_state_path: None,
+            debug: false,
+            trace_path: None,
+            trace_format: TraceFormat::default(),
+            trace_filter: TraceFilter::default(),
+            state_path: None,
+            rewind_seconds: 10,
+            rewind_mib: 16,
+            record_path: None,
+            play_path: None,
+            seed: None,
+            keymap_path: DEFAULT_KEYMAP_PATH.to_string(),
+            dead_zone: gamepad::DEFAULT_DEAD_ZONE,
+            expect_hash: None,
+        }
+    }
+}
+
+impl Config {
+    /// Parses the ROM path and options that follow the subcommand. Options
+    /// take their value as `--name value` or `--name=value`.
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Config, String> {
+        Config::build_layers([args.collect()])
+    }
+
+    /// Parses options from several sources, each overriding the ones
+    /// before. A layer's `--mode` also replaces the quirks earlier layers
+    /// gave, and its `--quirks` preset the mode, unless it sets both.
+    pub fn build_layers(layers: impl IntoIterator<Item = Vec<String>>) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut file_path = None;
+        let mut mode = None;
+        let mut quirks = None;
+        for layer in layers {
+            // Each with what its name implies for the other
+            let mut layer_mode = None;
+            let mut layer_quirks = None;
+            let mut args = layer.into_iter();
+            while let Some(arg) = args.next() {
+                let (flag, inline) = match arg.split_once('=') {
+                    Some((flag, value)) if flag.starts_with("--") => {
+                        (flag.to_string(), Some(value.to_string()))
+                    }
+                    _ => (arg.clone(), None),
+                };
+                let mut value = || {
+                    inline
+                        .clone()
+                        .or_else(|| args.next())
+                        .ok_or(format!("{flag} needs a value"))
+                };
+                match flag.as_str() {
+                    "--scale" => config.video_scale_factor = parse(&flag, &value()?)?,
+                    "--ipf" => config.instructions_per_frame = parse(&flag, &value()?)?,
+                    "--mode" => {
+                        let name = value()?;
+                        let mode = Mode::from_name(&name).ok_or(format!("unknown mode: {name}"))?;
+                        // Plain CHIP-8 keeps the default quirks, as if no mode
+                        // were given; `--quirks vip` asks for the VIP's
+                        let implied = match name.as_str() {
+                            "chip8" | "chip-8" => Some(Quirks::default()),
+                            _ => Quirks::from_name(&name),
+                        };
+                        layer_mode = Some((mode, implied));
+                    }
+                    "--quirks" => {
+                        let spec = value()?;
+                        let preset = spec.split(',').next().unwrap_or_default();
+                        layer_quirks = Some((Quirks::parse(&spec)?, Mode::from_name(preset)));
+                    }
+                    "--palette" => config.palette = Palette::parse(&value()?)?,
+                    "--frequency" => {
+                        let frequency: f32 = parse(&flag, &value()?)?;
+                        if !(frequency.is_finite() && frequency > 0.0) {
+                            return Err(format!("--frequency must be above 0, got {frequency}"));
+                        }
+                        config.audio.frequency = frequency;
+                    }
+                    "--volume" => {
+                        let volume: f32 = parse(&flag, &value()?)?;
+                        if !(0.0..=1.0).contains(&volume) {
+                            return Err(format!("--volume must be between 0 and 1, got {volume}"));
+                        }
+                        config.audio.volume = volume;
+                    }
+                    "--mute" => {
+                        config.audio.muted = match &inline {
+                            Some(muted) => parse(&flag, muted)?,
+                            None => true,
+                        }
+                    }
+                    "--seed" => config.seed = Some(parse(&flag, &value()?)?),
+                    "--headless" => config.headless = true,
+                    "--frames" => config.frames = parse(&flag, &value()?)?,
+                    "--instructions" => config.instructions = Some(parse(&flag, &value()?)?),
+                    "--keys" => config.key_script_path = Some(value()?),
+                    "--png" => config.png_path = Some(value()?),
+                    "--save-state" => config.save_state_path = Some(value()?),
+                    "--debug" => config.debug = true,
+                    "--trace" => config.trace_path = Some(value()?),
+                    "--trace-format" => {
+                        let name = value()?;
+                        config.trace_format = TraceFormat::from_name(&name)
+                            .ok_or(format!("unknown trace format: {name}"))?;
+                    }
+                    "--trace-range" => {
+                        config.trace_filter.range = Some(TraceFilter::parse_range(&value()?)?);
+                    }
+                    "--trace-ops" => {
+                        config.trace_filter.classes = TraceFilter::parse_classes(&value()?)?;
+                    }
+                    "--state" => config.state_path = Some(value()?),
+                    "--rewind" => config.rewind_seconds = parse(&flag, &value()?)?,
+                    "--rewind-mem" => {
+                        let mib: usize = parse(&flag, &value()?)?;
+                        if mib.checked_mul(1 << 20).is_none() {
+                            return Err(format!("--rewind-mem is too large, got {mib}"));
+                        }
+                        config.rewind_mib = mib;
+                    }
+                    "--record" => config.record_path = Some(value()?),
+                    "--play" | "--movie" => config.play_path = Some(value()?),
+                    "--keymap" => config.keymap_path = value()?,
+                    "--dead-zone" => {
+                        let dead_zone: i16 = parse(&flag, &value()?)?;
+                        if dead_zone < 0 {
+                            return Err(format!(
+                                "--dead-zone must not be negative, got {dead_zone}"
+                            ));
+                        }
+                        config.dead_zone = dead_zone;
+                    }
+                    "--expect" => {
+                        let hash = value()?;
+                        config.expect_hash = Some(
+                            u64::from_str_radix(hash.trim_start_matches("0x"), 16)
+                                .map_err(|_| format!("invalid value for --expect: {hash}"))?,
+                        );
+                    }
+                    _ if flag.starts_with('-') => return Err(format!("unknown option: {flag}")),
+                    _ if file_path.is_none() => file_path = Some(arg),
+                    _ => return Err(format!("unexpected argument: {arg}")),
+                }
+            }
+            match (layer_mode, layer_quirks) {
+                (Some((layer_mode, _)), Some((layer_quirks, _))) => {
+                    (mode, quirks) = (Some(layer_mode), Some(layer_quirks));
+                }
+                (Some((layer_mode, implied)), None) => (mode, quirks) = (Some(layer_mode), implied),
+                (None, Some((layer_quirks, implied))) => {
+                    (mode, quirks) = (implied.or(mode), Some(layer_quirks));
+                }
+                (None, None) => {}
+            }
+        }
+        if config.video_scale_factor == 0 {
+            return Err("--scale must be at least 1".to_string());
+        }
+        if config.instructions_per_frame == 0 {
+            return Err("--ipf must be at least 1".to_string());
+        }
+        config.file_path = file_path.ok_or("no ROM given")?;
+        config.mode = mode.unwrap_or_default();
+        config.quirks = quirks.unwrap_or_default();
+        Ok(config)
+    }
+
+    /// Rejects the options only a windowed run can act on.
+    pub fn check_headless(&self) -> Result<(), String> {
+        if self.record_path.is_some() {
+            return Err("--record needs a window; it can't be used headless".to_string());
+        }
+        if self.debug {
+            return Err("--debug needs a window; it can't be used headless".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Builds the config from the ROM database, the settings files and `args`,
+/// each winning over the ones before. Both are only read once the ROM is
+/// known, since what they say depends on its hash.
+pub fn configure(args: &[String]) -> Result<Config, String> {
+    let path = Config::build(args.iter().cloned())?.file_path;
+    // A missing ROM is reported when it is loaded
+    let hash = fs::read(&path)
+        .map(|rom| sha1::hex_digest(&rom))
+        .unwrap_or_default();
+    let settings = Settings::load(&settings::search_paths())?;
+    // The ROM database only fills in what nothing else sets
+    let db = RomDb::bundled()
+        .lookup(&hash)
+        .map(|info| info.args())
+        .unwrap_or_default();
+    Config::build_layers([
+        db,
+        settings.global_args(),
+        settings.rom_args(&hash),
+        args.to_vec(),
+    ])
+}
+
+fn parse<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {flag}: {value}"))
+}
+
+/// Builds the machine `config` describes: ROM, seed, save state and movie
+/// to play back.
+pub fn boot(config: &Config) -> Result<(Chip8, Option<(KeyScript, Movie)>), String> {
+    let mut chip8 = Chip8::with_mode(config.mode, config.quirks);
+    chip8
+        .load_rom(&config.file_path)
+        .map_err(|err| format!("Problem loading ROM @ {}: {err}", config.file_path))?;
+
+    let movie = match &config.play_path {
+        Some(path) => {
+            Some(Movie::read_file(path).map_err(|e| format!("Problem loading movie: {e}"))?)
+        }
+        None => None,
+    };
+
+    // Headless runs start from a fixed seed so their state hashes repeat
+    let seed = match (&movie, config.seed) {
+        (Some(movie), _) => movie.seed,
+        (None, Some(seed)) => seed,
+        (None, None) if config.headless => 0,
+        (None, None) => rand::random(),
+    };
+    chip8.set_seed(seed);
+
+    if let Some(path) = &config.state_path {
+        let state =
+            SaveState::read_file(path).map_err(|e| format!("Problem loading save state: {e}"))?;
+        chip8.load_state(state);
+    }
+
+    let playback = match movie {
+        Some(movie) => {
+            movie
+                .check_start(&chip8)
+                .map_err(|e| format!("Problem playing movie: {e}"))?;
+            Some((movie.key_script(), movie))
+        }
+        None => None,
+    };
+    Ok((chip8, playback))
+}
+
+/// How a headless run went.
+#[derive(Debug, Clone)]
+pub struct HeadlessRun {
+    pub report: RunReport,
+    pub state_hash: u64,
+    /// A fault, a desynced movie or an unexpected state hash; the run
+    /// passed if there are none.
+    pub failures: Vec<String>,
+}
+
+impl HeadlessRun {
+    /// The final screen, run length, state hash and registers, as printed
+    /// at the end of a headless run.
+    pub fn summary(&self, chip8: &Chip8) -> String {
+        let registers: Vec<String> = chip8
+            .registers()
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("V{i:X}={v:02X}"))
+            .collect();
+        format!(
+            "{}frames={} instructions={} state_hash={:016x}\n{}\nI={:04X} PC={:04X} SP={:X} DT={:02X} ST={:02X}\n",
+            headless::framebuffer_ascii(chip8.video(), chip8.display_width()),
+            self.report.frames,
+            self.report.instructions,
+            self.state_hash,
+            registers.join(" "),
+            chip8.index(),
+            chip8.pc(),
+            chip8.sp(),
+            chip8.delay_timer(),
+            chip8.sound_timer()
+        )
+    }
+}
+
+/// Runs a booted machine without a window for as long as `config` or the
+/// movie being played says, writing the trace, PNG and save state asked
+/// for. Errs if a file can't be read or written.
+pub fn run_headless(
+    config: &Config,
+    chip8: &mut Chip8,
+    playback: Option<(KeyScript, Movie)>,
+) -> Result<HeadlessRun, String> {
+    // A movie replaces the key script and fixes the run's length and pace
+    let (script, limit, ipf) = match &playback {
+        Some((script, movie)) => (
+            script.clone(),
+            RunLimit::Frames(movie.frames.len() as u64),
+            movie.instructions_per_frame,
+        ),
+        None => {
+            let script = match &config.key_script_path {
+                Some(path) => fs::read_to_string(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|src| KeyScript::parse(&src))
+                    .map_err(|e| format!("Problem loading key script @ {path}: {e}"))?,
+                None => KeyScript::default(),
+            };
+            let limit = match config.instructions {
+                Some(instructions) => RunLimit::Instructions(instructions),
+                None => RunLimit::Frames(config.frames),
+            };
+            (script, limit, config.instructions_per_frame)
+        }
+    };
+    let report = match &config.trace_path {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| format!("{path}: {e}"))?;
+            let mut trace = TraceWriter::new(BufWriter::new(file), config.trace_format);
+            chip8.set_trace(Some(config.trace_filter.clone()));
+            let report = headless::run_traced(chip8, limit, ipf, &script, &mut trace);
+            trace.finish().map_err(|e| format!("{path}: {e}"))?;
+            report
+        }
+        None => headless::run(chip8, limit, ipf, &script),
+    };
+
+    if let Some(path) = &config.png_path {
+        let image = png::encode_framebuffer_rgb(
+            chip8.video(),
+            chip8.display_width(),
+            chip8.display_height(),
+            config.video_scale_factor as usize,
+            &config.palette,
+        );
+        fs::write(path, image).map_err(|e| format!("Problem writing PNG @ {path}: {e}"))?;
+    }
+    if let Some(path) = &config.save_state_path {
+        chip8
+            .save_state()
+            .write_file(path)
+            .map_err(|e| format!("Problem writing save state: {e}"))?;
+    }
+
+    let state_hash = movie::state_hash(chip8);
+    let mut failures = Vec::new();
+    if let Some(err) = report.error {
+        failures.push(format!("{}: {err}", config.file_path));
+    }
+    if let Some((_, movie)) = &playback
+        && let Err(err) = movie.verify(chip8)
+    {
+        failures.push(format!("movie: {err}"));
+    }
+    if let Some(expected) = config.expect_hash
+        && expected != state_hash
+    {
+        failures.push(format!(
+            "state hash {state_hash:016x} does not match expected {expected:016x}"
+        ));
+    }
+    Ok(HeadlessRun {
+        report,
+        state_hash,
+        failures,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(args: &[&str]) -> Result<Config, String> {
+        Config::build(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn build_test() {
+        let config = build(&[
+            "rom.ch8",
+            "--scale=4",
+            "--ipf",
+            "30",
+            "--palette=amber",
+            "--rewind-mem",
+            "8",
+            "--mute",
+        ])
+        .unwrap();
+        assert_eq!(config.file_path, "rom.ch8");
+        assert_eq!(config.video_scale_factor, 4);
+        assert_eq!(config.instructions_per_frame, 30);
+        assert_eq!(config.palette, Palette::AMBER);
+        assert_eq!(config.rewind_mib, 8);
+        assert!(config.audio.muted);
+        let config = build(&["--mute", "rom.ch8", "--mute=false"]).unwrap();
+        assert_eq!(config.file_path, "rom.ch8");
+        assert!(!config.audio.muted);
+
+        // Only the first `=` splits, and single-dash arguments never do
+        let config = build(&["--trace=a=b.txt", "x=y.ch8"]).unwrap();
+        assert_eq!(config.trace_path.as_deref(), Some("a=b.txt"));
+        assert_eq!(config.file_path, "x=y.ch8");
+    }
+
+    #[test]
+    fn build_layers_test() {
+        let maze = sha1::hex_digest(include_bytes!("../maze.ch8"));
+        let db = RomDb::bundled().lookup(&maze).unwrap().args();
+        let layers = |cli: &[&str]| {
+            let cli = cli.iter().map(|arg| arg.to_string()).collect();
+            let config = Config::build_layers([db.clone(), cli]).unwrap();
+            (config.mode, config.quirks)
+        };
+        assert_eq!(layers(&["maze.ch8"]), (Mode::Chip8, Quirks::vip()));
+        assert_eq!(layers(&["maze.ch8", "--ipf=5"]).1, Quirks::vip());
+
+        // The command line's mode or quirks preset replaces both
+        let schip = (Mode::SuperChip, Quirks::schip());
+        assert_eq!(layers(&["maze.ch8", "--mode", "schip"]), schip);
+        assert_eq!(layers(&["maze.ch8", "--quirks", "schip"]), schip);
+        assert_eq!(
+            layers(&["maze.ch8", "--mode=xochip", "--quirks=vip"]),
+            (Mode::XoChip, Quirks::vip())
+        );
+        // Naming the default mode is the same as leaving it out
+        assert_eq!(
+            layers(&["maze.ch8", "--mode=chip8"]),
+            (Mode::Chip8, Quirks::default())
+        );
+        assert_eq!(
+            build(&["rom.ch8", "--mode=chip8"]).unwrap().quirks,
+            build(&["rom.ch8"]).unwrap().quirks
+        );
+        // A spec without a preset keeps the mode
+        assert_eq!(
+            layers(&["maze.ch8", "--quirks=default,+clip"]).0,
+            Mode::Chip8
+        );
+
+        // As does a per-ROM settings section
+        let config = Config::build_layers([
+            vec!["--mode=xochip".to_string()],
+            vec!["--quirks=chip48".to_string()],
+            vec!["maze.ch8".to_string()],
+        ])
+        .unwrap();
+        assert_eq!(config.mode, Mode::Chip8);
+        assert_eq!(config.quirks, Quirks::chip48());
+    }
+
+    #[test]
+    fn build_errors_test() {
+        let err = |args: &[&str]| build(args).unwrap_err();
+        assert_eq!(err(&["rom.ch8", "--frob"]), "unknown option: --frob");
+        assert_eq!(
+            err(&["rom.ch8", "--mute=no"]),
+            "invalid value for --mute: no"
+        );
+        assert_eq!(err(&["rom.ch8", "--frob=1"]), "unknown option: --frob");
+        assert_eq!(err(&["rom.ch8", "--ipf"]), "--ipf needs a value");
+        assert_eq!(
+            err(&["rom.ch8", "--ipf=ten"]),
+            "invalid value for --ipf: ten"
+        );
+        assert_eq!(err(&["a.ch8", "b.ch8"]), "unexpected argument: b.ch8");
+        assert_eq!(err(&["--scale", "2"]), "no ROM given");
+        assert_eq!(err(&["rom.ch8", "--scale=0"]), "--scale must be at least 1");
+        assert_eq!(err(&["rom.ch8", "--ipf=0"]), "--ipf must be at least 1");
+        assert_eq!(
+            err(&["rom.ch8", "--mode=megachip"]),
+            "unknown mode: megachip"
+        );
+        assert!(err(&["rom.ch8", "--quirks=schip,+frob"]).contains("frob"));
+        assert!(err(&["rom.ch8", "--palette=000000"]).contains("2 to 4 colours"));
+        assert_eq!(
+            err(&["rom.ch8", "--volume=1.5"]),
+            "--volume must be between 0 and 1, got 1.5"
+        );
+        for frequency in ["0", "-440", "NaN", "inf"] {
+            assert!(err(&["rom.ch8", "--frequency", frequency]).starts_with("--frequency"));
+        }
+        assert_eq!(
+            err(&["rom.ch8", "--trace-format=xml"]),
+            "unknown trace format: xml"
+        );
+        assert_eq!(
+            err(&["rom.ch8", "--dead-zone=-1"]),
+            "--dead-zone must not be negative, got -1"
+        );
+        assert_eq!(
+            err(&["rom.ch8", "--expect=xyz"]),
+            "invalid value for --expect: xyz"
+        );
+        assert!(err(&["rom.ch8", &format!("--rewind-mem={}", usize::MAX)]).contains("too large"));
+    }
+}
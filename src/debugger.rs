@@ -0,0 +1,691 @@
+//! Interactive debugger: breakpoints, single-stepping, step-over and
+//! run-to-address, driven by text commands so any frontend can feed it.
+
+use crate::chip8::Chip8;
+use crate::error::ExecError;
+use crate::instruction::Instruction;
+use crate::watch::{WatchHit, Watchpoint};
+use std::fmt;
+
+pub const HELP: &str = "\
+commands (numbers are decimal, or hex with a 0x or $ prefix):
+  s, step [N]          execute N instructions (default 1)
+  n, next              step, running CALLs through to their return
+  c, continue          resume until a breakpoint
+  p, pause             stop at the next instruction
+  u, until ADDR        run to ADDR
+  b, break ADDR        break when PC reaches ADDR
+  b, break op PATTERN  break on matching opcodes, X/Y/N are wildcards (e.g. DXYN)
+  b, break if REG OP VALUE
+                       break when a register compares true, REG is V0-VF or I,
+                       OP one of == != < <= > >=
+  d, delete N          remove breakpoint N
+  w, watch ADDR [LEN] [r|w|rw] [log]
+                       stop (or just log) when LEN bytes at ADDR are read
+                       and/or written (default 1 byte, rw)
+  unwatch N            remove watchpoint N
+  l, list              list breakpoints and watchpoints
+  r, regs              show registers
+  x ADDR [LEN]         dump LEN bytes of memory (default 16)
+  h, help              show this text";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(usize),
+    I,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Compare {
+    fn test(self, lhs: u16, rhs: u16) -> bool {
+        match self {
+            Compare::Eq => lhs == rhs,
+            Compare::Ne => lhs != rhs,
+            Compare::Lt => lhs < rhs,
+            Compare::Le => lhs <= rhs,
+            Compare::Gt => lhs > rhs,
+            Compare::Ge => lhs >= rhs,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Compare::Eq => "==",
+            Compare::Ne => "!=",
+            Compare::Lt => "<",
+            Compare::Le => "<=",
+            Compare::Gt => ">",
+            Compare::Ge => ">=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// PC reaches an address.
+    Pc(usize),
+    /// The next opcode matches `value` in the bits set in `mask`.
+    Opcode { mask: u16, value: u16 },
+    /// A register comparison holds.
+    Register {
+        reg: Register,
+        cmp: Compare,
+        value: u16,
+    },
+}
+
+impl Breakpoint {
+    fn hit(&self, chip8: &Chip8) -> bool {
+        match *self {
+            Breakpoint::Pc(addr) => chip8.pc() == addr,
+            Breakpoint::Opcode { mask, value } => {
+                chip8.current_opcode().is_some_and(|op| op & mask == value)
+            }
+            Breakpoint::Register { reg, cmp, value } => {
+                let lhs = match reg {
+                    Register::V(n) => chip8.registers()[n] as u16,
+                    Register::I => chip8.index(),
+                };
+                cmp.test(lhs, value)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Breakpoint::Pc(addr) => write!(f, "pc == 0x{addr:03X}"),
+            Breakpoint::Opcode { mask, value } => {
+                write!(f, "opcode ")?;
+                for shift in [12, 8, 4, 0] {
+                    let wildcard = ["N", "Y", "X", "N"][shift / 4];
+                    if (mask >> shift) & 0xF == 0 {
+                        write!(f, "{wildcard}")?;
+                    } else {
+                        write!(f, "{:X}", (value >> shift) & 0xF)?;
+                    }
+                }
+                Ok(())
+            }
+            Breakpoint::Register { reg, cmp, value } => {
+                match reg {
+                    Register::V(n) => write!(f, "V{n:X}")?,
+                    Register::I => write!(f, "I")?,
+                }
+                write!(f, " {} 0x{value:X}", cmp.symbol())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Step(u32),
+    Next,
+    Continue,
+    Pause,
+    Until(usize),
+    Break(Breakpoint),
+    Delete(usize),
+    Watch(Watchpoint),
+    Unwatch(usize),
+    List,
+    Regs,
+    Examine(usize, usize),
+    Help,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&name, args)) = words.split_first() else {
+            return Err("empty command".to_string());
+        };
+        let command = match (name, args) {
+            ("s" | "step", []) => Command::Step(1),
+            ("s" | "step", [n]) => Command::Step(
+                u32::try_from(parse_number(n)?).map_err(|_| format!("too many steps: {n}"))?,
+            ),
+            ("n" | "next", []) => Command::Next,
+            ("c" | "continue", []) => Command::Continue,
+            ("p" | "pause", []) => Command::Pause,
+            ("u" | "until", [addr]) => Command::Until(parse_number(addr)?),
+            ("b" | "break", ["op", pattern]) => Command::Break(parse_pattern(pattern)?),
+            ("b" | "break", ["if", reg, cmp, value]) => Command::Break(Breakpoint::Register {
+                reg: parse_register(reg)?,
+                cmp: parse_compare(cmp)?,
+                value: u16::try_from(parse_number(value)?)
+                    .map_err(|_| format!("value out of range: {value}"))?,
+            }),
+            ("b" | "break", [addr]) => Command::Break(Breakpoint::Pc(parse_number(addr)?)),
+            ("d" | "delete", [n]) => Command::Delete(parse_number(n)?),
+            ("w" | "watch", [addr, rest @ ..]) => Command::Watch(parse_watch(addr, rest)?),
+            ("unwatch", [n]) => Command::Unwatch(parse_number(n)?),
+            ("l" | "list", []) => Command::List,
+            ("r" | "regs", []) => Command::Regs,
+            ("x", [addr]) => Command::Examine(parse_number(addr)?, 16),
+            ("x", [addr, len]) => Command::Examine(parse_number(addr)?, parse_number(len)?),
+            ("h" | "help", []) => Command::Help,
+            _ => return Err(format!("unknown command: {line} (try `help`)")),
+        };
+        Ok(command)
+    }
+}
+
+fn parse_number(s: &str) -> Result<usize, String> {
+    let hex = s.strip_prefix("0x").or_else(|| s.strip_prefix('$'));
+    match hex {
+        Some(digits) => usize::from_str_radix(digits, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| format!("invalid number: {s}"))
+}
+
+fn parse_register(s: &str) -> Result<Register, String> {
+    let upper = s.to_ascii_uppercase();
+    if upper == "I" {
+        return Ok(Register::I);
+    }
+    upper
+        .strip_prefix('V')
+        .filter(|n| n.len() == 1)
+        .and_then(|n| usize::from_str_radix(n, 16).ok())
+        .map(Register::V)
+        .ok_or(format!("invalid register: {s}"))
+}
+
+fn parse_compare(s: &str) -> Result<Compare, String> {
+    Ok(match s {
+        "==" => Compare::Eq,
+        "!=" => Compare::Ne,
+        "<" => Compare::Lt,
+        "<=" => Compare::Le,
+        ">" => Compare::Gt,
+        ">=" => Compare::Ge,
+        _ => return Err(format!("invalid comparison: {s}")),
+    })
+}
+
+/// Parses an opcode pattern such as `DXYN` or `00E0` into a mask/value pair.
+fn parse_pattern(s: &str) -> Result<Breakpoint, String> {
+    if s.len() != 4 {
+        return Err(format!("opcode pattern must be 4 digits: {s}"));
+    }
+    let (mut mask, mut value) = (0u16, 0u16);
+    for c in s.chars() {
+        mask <<= 4;
+        value <<= 4;
+        match c.to_ascii_uppercase() {
+            'X' | 'Y' | 'N' => {}
+            c => {
+                let digit = c
+                    .to_digit(16)
+                    .ok_or(format!("invalid opcode pattern: {s}"))?;
+                mask |= 0xF;
+                value |= digit as u16;
+            }
+        }
+    }
+    Ok(Breakpoint::Opcode { mask, value })
+}
+
+/// Parses the arguments of `watch ADDR [LEN] [r|w|rw] [log]`.
+fn parse_watch(addr: &str, rest: &[&str]) -> Result<Watchpoint, String> {
+    let start = parse_number(addr)?;
+    let mut watchpoint = Watchpoint {
+        range: start..start.saturating_add(1),
+        on_read: true,
+        on_write: true,
+        log_only: false,
+    };
+    for (i, &arg) in rest.iter().enumerate() {
+        match arg {
+            "r" => (watchpoint.on_read, watchpoint.on_write) = (true, false),
+            "w" => (watchpoint.on_read, watchpoint.on_write) = (false, true),
+            "rw" => (watchpoint.on_read, watchpoint.on_write) = (true, true),
+            "log" => watchpoint.log_only = true,
+            len if i == 0 => {
+                watchpoint.range = start..start.saturating_add(parse_number(len)?.max(1))
+            }
+            _ => return Err(format!("invalid watch argument: {arg}")),
+        }
+    }
+    Ok(watchpoint)
+}
+
+/// Why execution stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint(usize),
+    Step,
+    Watch(WatchHit),
+    Fault(ExecError),
+}
+
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    paused: bool,
+    steps_left: u32,
+    /// Temporary stop for `next`/`until`: an address and, for `next`, the
+    /// stack depth the CALL returns to.
+    run_to: Option<(usize, Option<usize>)>,
+    /// Set on resume so the breakpoint we're sitting on doesn't fire again.
+    resuming: bool,
+    /// Hits on log-only watchpoints, waiting to be shown.
+    log: Vec<String>,
+    /// Instructions already run in a frame a stop interrupted.
+    frame_done: u32,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.steps_left = 0;
+        self.run_to = None;
+    }
+
+    /// Whether a stop interrupted the current frame, which the next
+    /// [`Debugger::run_frame`] finishes rather than starting a new one.
+    pub fn mid_frame(&self) -> bool {
+        self.frame_done > 0
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Drains the messages from log-only watchpoints.
+    pub fn take_log(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.log)
+    }
+
+    /// Applies a command and returns the text to show the user.
+    pub fn execute(&mut self, command: Command, chip8: &mut Chip8) -> String {
+        match command {
+            Command::Step(n) => {
+                self.resume();
+                self.steps_left = n.max(1);
+                String::new()
+            }
+            Command::Next => {
+                self.resume();
+                match chip8.current_opcode() {
+                    Some(op) if op & 0xF000 == 0x2000 => {
+                        self.run_to = Some((chip8.pc() + 2, Some(chip8.sp())));
+                    }
+                    _ => self.steps_left = 1,
+                }
+                String::new()
+            }
+            Command::Continue => {
+                self.resume();
+                String::new()
+            }
+            Command::Pause => {
+                self.pause();
+                describe(chip8)
+            }
+            Command::Until(addr) => {
+                self.resume();
+                self.run_to = Some((addr, None));
+                String::new()
+            }
+            Command::Break(bp) => {
+                self.breakpoints.push(bp);
+                format!("breakpoint {}: {bp}", self.breakpoints.len() - 1)
+            }
+            Command::Delete(n) if n < self.breakpoints.len() => {
+                let bp = self.breakpoints.remove(n);
+                format!("deleted breakpoint {n}: {bp}")
+            }
+            Command::Delete(n) => format!("no breakpoint {n}"),
+            Command::Watch(wp) => {
+                let text = wp.to_string();
+                let n = chip8.add_watchpoint(wp);
+                format!("watchpoint {n}: {text}")
+            }
+            Command::Unwatch(n) => match chip8.remove_watchpoint(n) {
+                Some(wp) => format!("deleted watchpoint {n}: {wp}"),
+                None => format!("no watchpoint {n}"),
+            },
+            Command::List => {
+                let mut lines: Vec<String> = self
+                    .breakpoints
+                    .iter()
+                    .enumerate()
+                    .map(|(i, bp)| format!("breakpoint {i}: {bp}"))
+                    .collect();
+                lines.extend(
+                    chip8
+                        .watchpoints()
+                        .iter()
+                        .enumerate()
+                        .map(|(i, wp)| format!("watchpoint {i}: {wp}")),
+                );
+                if lines.is_empty() {
+                    return "no breakpoints".to_string();
+                }
+                lines.join("\n")
+            }
+            Command::Regs => describe(chip8),
+            Command::Examine(addr, len) => {
+                let memory = chip8.memory();
+                let end = addr.saturating_add(len).min(memory.len());
+                let lines: Vec<String> = memory[addr.min(end)..end]
+                    .chunks(16)
+                    .enumerate()
+                    .map(|(i, chunk)| {
+                        let bytes: Vec<String> = chunk.iter().map(|b| format!("{b:02X}")).collect();
+                        format!("0x{:03X}: {}", addr + i * 16, bytes.join(" "))
+                    })
+                    .collect();
+                lines.join("\n")
+            }
+            Command::Help => HELP.to_string(),
+        }
+    }
+
+    fn resume(&mut self) {
+        self.paused = false;
+        self.resuming = true;
+        self.steps_left = 0;
+        self.run_to = None;
+    }
+
+    /// Checks whether execution should stop before the next instruction.
+    fn check(&mut self, chip8: &Chip8) -> Option<StopReason> {
+        if let Some((addr, depth)) = self.run_to
+            && chip8.pc() == addr
+            && depth.is_none_or(|sp| chip8.sp() == sp)
+        {
+            return Some(StopReason::Step);
+        }
+        if std::mem::take(&mut self.resuming) {
+            return None;
+        }
+        self.breakpoints
+            .iter()
+            .position(|bp| bp.hit(chip8))
+            .map(StopReason::Breakpoint)
+    }
+
+    /// Runs up to one frame's worth of instructions, stopping early on a
+    /// breakpoint, a finished step or a fault. A frame cut short is finished
+    /// by the next call, and timers only tick once it is, so time stands
+    /// still while paused and every frame runs the same instructions.
+    pub fn run_frame(
+        &mut self,
+        chip8: &mut Chip8,
+        instructions_per_frame: u32,
+    ) -> Option<StopReason> {
+        if self.paused {
+            return None;
+        }
+        while self.frame_done < instructions_per_frame {
+            if let Some(reason) = self.check(chip8) {
+                self.pause();
+                return Some(reason);
+            }
+            let result = chip8.cycle();
+            self.frame_done += 1;
+            let mut stop = None;
+            for hit in chip8.take_watch_hits() {
+                if chip8.watchpoints()[hit.watchpoint].log_only {
+                    self.log.push(hit.to_string());
+                } else {
+                    stop = stop.or(Some(hit));
+                }
+            }
+            if let Err(err) = result {
+                self.pause();
+                return Some(StopReason::Fault(err));
+            }
+            if let Some(hit) = stop {
+                self.pause();
+                return Some(StopReason::Watch(hit));
+            }
+            if self.steps_left > 0 {
+                self.steps_left -= 1;
+                if self.steps_left == 0 {
+                    self.pause();
+                    return Some(StopReason::Step);
+                }
+            }
+        }
+        self.frame_done = 0;
+        chip8.tick_timers();
+        None
+    }
+
+    /// Runs the rest of a frame a stop interrupted, ignoring breakpoints,
+    /// so that a movie being recorded ends on a whole frame.
+    pub fn finish_frame(
+        &mut self,
+        chip8: &mut Chip8,
+        instructions_per_frame: u32,
+    ) -> Result<(), ExecError> {
+        if self.frame_done == 0 {
+            return Ok(());
+        }
+        for _ in self.frame_done..instructions_per_frame {
+            chip8.cycle()?;
+        }
+        self.frame_done = 0;
+        chip8.tick_timers();
+        Ok(())
+    }
+
+    /// Human-readable report of why execution stopped.
+    pub fn report(&self, reason: StopReason, chip8: &Chip8) -> String {
+        let why = match reason {
+            StopReason::Breakpoint(n) => format!("hit breakpoint {n}: {}\n", self.breakpoints[n]),
+            StopReason::Watch(hit) => format!("{hit}\n"),
+            StopReason::Fault(err) => format!("fault: {err}\n"),
+            StopReason::Step => String::new(),
+        };
+        why + &describe(chip8)
+    }
+}
+
+/// One-screen summary of the machine: next instruction, registers, I, SP.
+fn describe(chip8: &Chip8) -> String {
+    let opcode = match chip8.current_opcode() {
+        Some(op) => match Instruction::decode(op) {
+            Ok(instruction) => format!("{op:04X} {instruction}"),
+            Err(_) => format!("{op:04X} ????"),
+        },
+        None => "----".to_string(),
+    };
+    let regs: Vec<String> = chip8
+        .registers()
+        .iter()
+        .enumerate()
+        .map(|(i, v)| format!("V{i:X}={v:02X}"))
+        .collect();
+    format!(
+        "PC=0x{:03X} [{opcode}] I=0x{:03X} SP={:X} DT={:02X} ST={:02X}\n{}",
+        chip8.pc(),
+        chip8.index(),
+        chip8.sp(),
+        chip8.delay_timer(),
+        chip8.sound_timer(),
+        regs.join(" ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x200 CALL 0x208; 0x202 LD V1, 0x05; 0x204 DRW V0, V0, 1; 0x206 JP 0x206
+    // 0x208 LD V0, 0x03; 0x20A RET
+    const ROM: [u8; 12] = [
+        0x22, 0x08, 0x61, 0x05, 0xD0, 0x01, 0x12, 0x06, 0x60, 0x03, 0x00, 0xEE,
+    ];
+
+    fn machine() -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&ROM).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(Command::parse("step"), Ok(Command::Step(1)));
+        assert_eq!(Command::parse("s 0x10"), Ok(Command::Step(16)));
+        assert_eq!(
+            Command::parse("b $202"),
+            Ok(Command::Break(Breakpoint::Pc(0x202)))
+        );
+        assert_eq!(
+            Command::parse("break op DXYN"),
+            Ok(Command::Break(Breakpoint::Opcode {
+                mask: 0xF000,
+                value: 0xD000
+            }))
+        );
+        assert_eq!(
+            Command::parse("break if vA >= 10"),
+            Ok(Command::Break(Breakpoint::Register {
+                reg: Register::V(0xA),
+                cmp: Compare::Ge,
+                value: 10
+            }))
+        );
+        assert!(Command::parse("break if V10 == 1").is_err());
+        assert_eq!(
+            Command::parse("break if I == 0x10000"),
+            Err("value out of range: 0x10000".to_string())
+        );
+        assert!(Command::parse("break op DXY").is_err());
+        assert!(Command::parse("frobnicate").is_err());
+        assert!(Command::parse("step 0x100000000").is_err());
+        assert_eq!(
+            Breakpoint::Opcode {
+                mask: 0xF00F,
+                value: 0x8004
+            }
+            .to_string(),
+            "opcode 8XY4"
+        );
+    }
+
+    #[test]
+    fn breakpoint_test() {
+        let mut chip8 = machine();
+        let mut debugger = Debugger::new();
+        debugger.execute(Command::parse("break op DXYN").unwrap(), &mut chip8);
+        debugger.execute(Command::parse("break if V1 == 5").unwrap(), &mut chip8);
+
+        // Both hold at 0x204; the first one set wins
+        assert_eq!(
+            debugger.run_frame(&mut chip8, 100),
+            Some(StopReason::Breakpoint(0))
+        );
+        assert_eq!(chip8.pc(), 0x204);
+        assert!(debugger.is_paused());
+        assert!(debugger.mid_frame());
+        assert_eq!(debugger.run_frame(&mut chip8, 100), None);
+
+        // Continuing doesn't re-trigger on the spot we're stopped at, and
+        // only finishes the frame that was cut short
+        debugger.execute(Command::Delete(1), &mut chip8);
+        debugger.execute(Command::Continue, &mut chip8);
+        assert_eq!(debugger.run_frame(&mut chip8, 100), None);
+        assert_eq!(chip8.pc(), 0x206);
+        assert_eq!(chip8.cycles(), 100);
+        assert!(!debugger.mid_frame());
+
+        debugger.execute(Command::Step(10), &mut chip8);
+        assert_eq!(debugger.run_frame(&mut chip8, 100), Some(StopReason::Step));
+        assert_eq!(chip8.cycles(), 110);
+        assert_eq!(debugger.finish_frame(&mut chip8, 100), Ok(()));
+        assert_eq!(chip8.cycles(), 200);
+        assert!(!debugger.mid_frame());
+    }
+
+    #[test]
+    fn step_test() {
+        let mut chip8 = machine();
+        let mut debugger = Debugger::new();
+        debugger.pause();
+
+        // `next` runs the whole subroutine
+        debugger.execute(Command::Next, &mut chip8);
+        assert_eq!(debugger.run_frame(&mut chip8, 100), Some(StopReason::Step));
+        assert_eq!(chip8.pc(), 0x202);
+        assert_eq!(chip8.registers()[0], 3);
+
+        debugger.execute(Command::Step(2), &mut chip8);
+        assert_eq!(debugger.run_frame(&mut chip8, 100), Some(StopReason::Step));
+        assert_eq!(chip8.pc(), 0x206);
+
+        let mut chip8 = machine();
+        debugger.execute(Command::Until(0x20A), &mut chip8);
+        assert_eq!(debugger.run_frame(&mut chip8, 100), Some(StopReason::Step));
+        assert_eq!(chip8.pc(), 0x20A);
+        assert_eq!(chip8.sp(), 1);
+
+        let dump = debugger.execute(Command::Examine(0xFF8, usize::MAX), &mut chip8);
+        assert_eq!(dump, "0xFF8: 00 00 00 00 00 00 00 00");
+    }
+
+    #[test]
+    fn watch_test() {
+        assert_eq!(
+            Command::parse("watch 0x300 4 w log"),
+            Ok(Command::Watch(Watchpoint {
+                range: 0x300..0x304,
+                on_read: false,
+                on_write: true,
+                log_only: true,
+            }))
+        );
+        assert!(Command::parse("watch 0x300 4 x").is_err());
+        assert_eq!(
+            Command::parse(&format!("watch 0x300 {}", usize::MAX)),
+            Ok(Command::Watch(Watchpoint {
+                range: 0x300..usize::MAX,
+                on_read: true,
+                on_write: true,
+                log_only: false,
+            }))
+        );
+
+        // LD I, 0x300; LD V0, 0x01; LD [I], V0; JP 0x206
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom_bytes(&[0xA3, 0x00, 0x60, 0x01, 0xF0, 0x55, 0x12, 0x06])
+            .unwrap();
+        let mut debugger = Debugger::new();
+        debugger.execute(Command::parse("w 0x300 r log").unwrap(), &mut chip8);
+        debugger.execute(Command::parse("w 0x300 w").unwrap(), &mut chip8);
+        match debugger.run_frame(&mut chip8, 100) {
+            Some(StopReason::Watch(hit)) => {
+                assert_eq!((hit.watchpoint, hit.pc, hit.value), (1, 0x204, 0x01));
+            }
+            other => panic!("expected a watchpoint stop, got {other:?}"),
+        }
+        assert_eq!(chip8.pc(), 0x206);
+        assert!(debugger.take_log().is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+//! Static disassembler. Code is found by recursive descent from the entry
+//! point, following jumps, calls and both sides of every skip; whatever is
+//! never reached is listed as data, drawn as sprite rows.
+
+use crate::chip8::{Mode, START_ADDRESS};
+use crate::instruction::Instruction;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LabelKind {
+    /// Target of a CALL.
+    Sub,
+    /// Target of a JP.
+    Label,
+    /// Loaded into I, usually sprite or table data.
+    Data,
+}
+
+/// A disassembled ROM: which bytes are code, and the labels that point
+/// into it.
+#[derive(Debug, Clone)]
+pub struct Listing {
+    rom: Vec<u8>,
+    /// Instructions by start address.
+    code: BTreeMap<usize, Instruction>,
+    labels: BTreeMap<usize, LabelKind>,
+}
+
+impl Listing {
+    pub fn is_code(&self, addr: usize) -> bool {
+        self.code.contains_key(&addr)
+    }
+
+    pub fn instruction(&self, addr: usize) -> Option<Instruction> {
+        self.code.get(&addr).copied()
+    }
+
+    pub fn labels(&self) -> &BTreeMap<usize, LabelKind> {
+        &self.labels
+    }
+
+    /// Instructions by start address.
+    pub fn code(&self) -> &BTreeMap<usize, Instruction> {
+        &self.code
+    }
+
+    /// The most basic platform that has every instruction found.
+    pub fn platform(&self) -> Mode {
+        let mut platform = Mode::Chip8;
+        for instruction in self.code.values() {
+            match instruction.platform() {
+                Mode::XoChip => return Mode::XoChip,
+                Mode::SuperChip => platform = Mode::SuperChip,
+                Mode::Chip8 => {}
+            }
+        }
+        platform
+    }
+
+    pub fn label_name(&self, addr: usize) -> Option<String> {
+        let prefix = match self.labels.get(&addr)? {
+            LabelKind::Sub => "sub",
+            LabelKind::Label => "label",
+            LabelKind::Data => "data",
+        };
+        Some(format!("{prefix}_{addr:03X}"))
+    }
+
+    fn byte(&self, addr: usize) -> u8 {
+        self.rom[addr - START_ADDRESS]
+    }
+
+    fn word(&self, addr: usize) -> u16 {
+        u16::from_be_bytes([self.byte(addr), self.byte(addr + 1)])
+    }
+
+    fn end(&self) -> usize {
+        START_ADDRESS + self.rom.len()
+    }
+
+    /// Formats an address operand, by label name when there is one.
+    fn operand(&self, addr: usize) -> String {
+        self.label_name(addr)
+            .unwrap_or_else(|| format!("$0x{addr:03X}"))
+    }
+
+    /// Renders the listing: one line per instruction or data byte, with the
+    /// address, raw bytes and mnemonic, and label lines where referenced.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut addr = START_ADDRESS;
+        while addr < self.end() {
+            if let Some(name) = self.label_name(addr) {
+                let _ = writeln!(out, "{name}:");
+            }
+            match self.code.get(&addr) {
+                Some(Instruction::LoadLong) => {
+                    let target = self.word(addr + 2);
+                    let raw = format!("F000 {target:04X}");
+                    let text = format!("LD I, long {}", self.operand(target as usize));
+                    let _ = writeln!(out, "0x{addr:03X}: {raw:<9}    {text}");
+                    addr += 4;
+                }
+                Some(instruction) => {
+                    let raw = format!("{:04X}", instruction.encode());
+                    let text = instruction.format_with(|a| self.operand(a as usize));
+                    let _ = writeln!(out, "0x{addr:03X}: {raw:<9}    {text}");
+                    addr += 2;
+                }
+                None => {
+                    let byte = self.byte(addr);
+                    let sprite: String = (0..8)
+                        .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                        .collect();
+                    let _ = writeln!(
+                        out,
+                        "0x{addr:03X}: {byte:02X}           db 0x{byte:02X}    ; {sprite}"
+                    );
+                    addr += 1;
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Where control can go after `instruction` at `addr`. `size_at` gives the
+/// size of the instruction at an address, so skips clear XO-CHIP's 4-byte
+/// long load.
+fn successors(
+    instruction: Instruction,
+    addr: usize,
+    size_at: impl Fn(usize) -> usize,
+) -> Vec<usize> {
+    use Instruction::*;
+    let after = addr + instruction.size();
+    match instruction {
+        Jump(nnn) => vec![nnn as usize],
+        Call(nnn) => vec![nnn as usize, after],
+        SkipEqImm { .. }
+        | SkipNeImm { .. }
+        | SkipEq { .. }
+        | SkipNe { .. }
+        | SkipKey(_)
+        | SkipNotKey(_) => vec![after, after + size_at(after)],
+        // Computed jump; the target depends on a register
+        JumpV0(_) => vec![],
+        Return | Exit => vec![],
+        _ => vec![after],
+    }
+}
+
+/// Disassembles a ROM as loaded at 0x200.
+pub fn disassemble(rom: &[u8], mode: Mode) -> Listing {
+    let mut listing = Listing {
+        rom: rom.to_vec(),
+        code: BTreeMap::new(),
+        labels: BTreeMap::new(),
+    };
+    let end = listing.end();
+    let word = |addr: usize| -> Option<u16> {
+        (addr >= START_ADDRESS && addr + 1 < end)
+            .then(|| u16::from_be_bytes([rom[addr - START_ADDRESS], rom[addr + 1 - START_ADDRESS]]))
+    };
+    // Machine code calls are almost always zero padding or data
+    let decode = |addr: usize| {
+        word(addr)
+            .and_then(|op| Instruction::decode(op).ok())
+            .filter(|i| i.supported_in(mode) && !matches!(i, Instruction::Sys(_)))
+    };
+    let size_at = |addr: usize| decode(addr).map_or(2, |i| i.size());
+
+    let mut pending = vec![START_ADDRESS];
+    let mut seen = BTreeSet::new();
+    while let Some(addr) = pending.pop() {
+        if !seen.insert(addr) {
+            continue;
+        }
+        let Some(instruction) = decode(addr) else {
+            continue;
+        };
+        if addr + instruction.size() > end {
+            continue;
+        }
+        listing.code.insert(addr, instruction);
+
+        let kind = match instruction {
+            Instruction::Call(_) => LabelKind::Sub,
+            Instruction::Jump(_) => LabelKind::Label,
+            _ => LabelKind::Data,
+        };
+        let target = match instruction {
+            Instruction::LoadLong => word(addr + 2),
+            _ => instruction.target(),
+        };
+        if let Some(target) = target {
+            let label = listing.labels.entry(target as usize).or_insert(kind);
+            // A subroutine is a subroutine however else it is reached
+            if kind == LabelKind::Sub {
+                *label = kind;
+            }
+        }
+        pending.extend(successors(instruction, addr, size_at));
+    }
+    // Labels that point outside the ROM or into the middle of an
+    // instruction have nothing to attach to.
+    let code = listing.code.clone();
+    listing.labels.retain(|&addr, _| {
+        addr >= START_ADDRESS
+            && addr < end
+            && code
+                .range(..addr)
+                .next_back()
+                .is_none_or(|(&start, instruction)| addr >= start + instruction.size())
+    });
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maze_test() {
+        let rom = std::fs::read("maze.ch8").expect("should read maze.ch8");
+        let listing = disassemble(&rom, Mode::Chip8);
+        // maze is 30 bytes of code followed by two 4-byte sprites
+        assert!(listing.is_code(0x200));
+        assert!(listing.is_code(0x21C));
+        assert!(!listing.is_code(0x21E));
+        assert_eq!(listing.labels().get(&0x21E), Some(&LabelKind::Data));
+        assert_eq!(listing.labels().get(&0x222), Some(&LabelKind::Data));
+
+        let text = listing.render();
+        assert!(text.contains("0x200: 6000         LD V0, 0x000"));
+        assert!(text.contains("LD I, data_21E"));
+        assert!(text.contains("data_21E:\n0x21E: 80           db 0x80    ; #......."));
+    }
+
+    #[test]
+    fn descent_test() {
+        let rom = [
+            0x22, 0x08, // 0x200 CALL 0x208
+            0x30, 0x01, // 0x202 SE V0, 1
+            0x12, 0x02, // 0x204 JP 0x202
+            0x12, 0x06, // 0x206 JP 0x206
+            0x00, 0xEE, // 0x208 RET
+            0xFF, 0xFF, // 0x20A unreachable
+        ];
+        let listing = disassemble(&rom, Mode::Chip8);
+        for addr in [0x200, 0x202, 0x204, 0x206, 0x208] {
+            assert!(listing.is_code(addr), "0x{addr:03X} should be code");
+        }
+        assert!(!listing.is_code(0x20A));
+        assert_eq!(listing.label_name(0x208), Some("sub_208".to_string()));
+        assert_eq!(listing.label_name(0x202), Some("label_202".to_string()));
+        assert!(listing.render().contains("CALL sub_208"));
+        assert_eq!(listing.platform(), Mode::Chip8);
+
+        // The skip over XO-CHIP's long load lands after all four bytes
+        let rom = [
+            0x30, 0x01, 0xF0, 0x00, 0x02, 0x0A, 0x12, 0x06, 0x12, 0x08, 0xAB,
+        ];
+        let listing = disassemble(&rom, Mode::XoChip);
+        assert!(listing.is_code(0x206));
+        assert!(!listing.is_code(0x204));
+        assert!(listing.render().contains("LD I, long data_20A"));
+        assert_eq!(listing.platform(), Mode::XoChip);
+    }
+}
@@ -0,0 +1,37 @@
+use std::error::Error;
+use std::fmt;
+
+/// Why the machine stopped executing. Every variant carries the address of
+/// the instruction that faulted so frontends can report where a ROM died.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecError {
+    /// The opcode does not decode to any known instruction.
+    IllegalOpcode { opcode: u16, addr: u16 },
+    /// `CALL` with all 16 stack slots in use.
+    StackOverflow { addr: u16 },
+    /// `RET` with an empty stack.
+    StackUnderflow { addr: u16 },
+    /// An access through I (or a sprite read) ran past the end of memory.
+    MemoryOutOfBounds { addr: u16, index: usize },
+    /// The program counter left addressable memory.
+    PcOutOfRange { pc: usize },
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ExecError::IllegalOpcode { opcode, addr } => {
+                write!(f, "illegal opcode {opcode:04X} at 0x{addr:03X}")
+            }
+            ExecError::StackOverflow { addr } => write!(f, "stack overflow at 0x{addr:03X}"),
+            ExecError::StackUnderflow { addr } => write!(f, "stack underflow at 0x{addr:03X}"),
+            ExecError::MemoryOutOfBounds { addr, index } => write!(
+                f,
+                "memory access out of bounds (I=0x{index:04X}) at 0x{addr:03X}"
+            ),
+            ExecError::PcOutOfRange { pc } => write!(f, "program counter out of range: 0x{pc:04X}"),
+        }
+    }
+}
+
+impl Error for ExecError {}
@@ -0,0 +1,115 @@
+//! Game controller input as keymap names, so controllers are bound with the
+//! same [`crate::Keymap`] files and per-ROM sections as the keyboard.
+//!
+//! A button is `Pad <button>` using SDL's controller button names
+//! (`Pad a`, `Pad dpup`, `Pad leftshoulder`); a stick or trigger pushed
+//! past the dead zone is `Pad <axis>-` or `Pad <axis>+` (`Pad leftx-` is
+//! the left stick pushed left, `Pad righttrigger+` a pulled trigger).
+
+use std::collections::HashMap;
+
+/// How far from centre an axis has to move to count as pressed, out of
+/// 32767.
+pub const DEFAULT_DEAD_ZONE: i16 = 8000;
+
+pub fn button_name(button: &str) -> String {
+    format!("Pad {button}")
+}
+
+/// Turns analog axis positions into digital presses and releases.
+#[derive(Debug, Clone)]
+pub struct AxisTracker {
+    dead_zone: i16,
+    /// The direction each axis is pushed in, for axes that are pushed.
+    held: HashMap<String, i8>,
+}
+
+impl Default for AxisTracker {
+    fn default() -> Self {
+        AxisTracker::new(DEFAULT_DEAD_ZONE)
+    }
+}
+
+impl AxisTracker {
+    pub fn new(dead_zone: i16) -> AxisTracker {
+        AxisTracker {
+            dead_zone: dead_zone.max(0),
+            held: HashMap::new(),
+        }
+    }
+
+    /// Records that `axis` moved to `value` and returns the inputs that
+    /// were released or pressed as a result, as (name, pressed).
+    pub fn update(&mut self, axis: &str, value: i16) -> Vec<(String, bool)> {
+        let direction = if value > self.dead_zone {
+            1
+        } else if value < -self.dead_zone {
+            -1
+        } else {
+            0
+        };
+        let previous = self.held.get(axis).copied().unwrap_or(0);
+        if direction == previous {
+            return Vec::new();
+        }
+        if direction == 0 {
+            self.held.remove(axis);
+        } else {
+            self.held.insert(axis.to_string(), direction);
+        }
+        let name = |direction: i8| {
+            let sign = if direction > 0 { '+' } else { '-' };
+            format!("Pad {axis}{sign}")
+        };
+        let mut changes = Vec::new();
+        if previous != 0 {
+            changes.push((name(previous), false));
+        }
+        if direction != 0 {
+            changes.push((name(direction), true));
+        }
+        changes
+    }
+
+    /// Releases every pushed axis, e.g. when a controller is unplugged.
+    pub fn release_all(&mut self) -> Vec<(String, bool)> {
+        let axes: Vec<String> = self.held.keys().cloned().collect();
+        axes.iter().flat_map(|axis| self.update(axis, 0)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn axis_test() {
+        let mut axes = AxisTracker::default();
+        assert!(axes.update("leftx", 4000).is_empty());
+        assert_eq!(
+            axes.update("leftx", -20000),
+            [("Pad leftx-".to_string(), true)]
+        );
+        assert!(axes.update("leftx", -30000).is_empty());
+        // Flicking straight across releases one direction and presses the other
+        assert_eq!(
+            axes.update("leftx", 32767),
+            [
+                ("Pad leftx-".to_string(), false),
+                ("Pad leftx+".to_string(), true)
+            ]
+        );
+        axes.update("lefty", 9000);
+        let mut released = axes.release_all();
+        released.sort();
+        assert_eq!(
+            released,
+            [
+                ("Pad leftx+".to_string(), false),
+                ("Pad lefty+".to_string(), false)
+            ]
+        );
+        assert!(axes.update("leftx", 100).is_empty());
+        assert_eq!(button_name("dpup"), "Pad dpup");
+    }
+}
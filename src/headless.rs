@@ -0,0 +1,256 @@
+//! Display-less execution: run a ROM for a fixed budget with an optional
+//! scripted key timeline, then inspect the resulting machine state.
+
+use crate::chip8::Chip8;
+use crate::error::ExecError;
+use crate::trace::TraceSink;
+
+/// How long a headless run lasts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunLimit {
+    Frames(u64),
+    Instructions(u64),
+}
+
+/// A key going down or up at the start of a given frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// Key timeline for a headless run, one event per line:
+///
+/// ```text
+/// # frame key down|up
+/// 30 5 down
+/// 45 5 up
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyScript {
+    events: Vec<KeyEvent>,
+}
+
+impl KeyScript {
+    pub fn new(mut events: Vec<KeyEvent>) -> KeyScript {
+        events.sort_by_key(|e| e.frame);
+        KeyScript { events }
+    }
+
+    pub fn parse(src: &str) -> Result<KeyScript, String> {
+        let mut events = Vec::new();
+        for (i, line) in src.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |msg: &str| format!("line {}: {msg}: {line}", i + 1);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [frame, key, action] = fields[..] else {
+                return Err(err("expected `frame key down|up`"));
+            };
+            let frame = frame.parse().map_err(|_| err("invalid frame"))?;
+            let key = u8::from_str_radix(key.trim_start_matches("0x"), 16)
+                .ok()
+                .filter(|&k| k < 16)
+                .ok_or_else(|| err("invalid key"))?;
+            let pressed = match action {
+                "down" => true,
+                "up" => false,
+                _ => return Err(err("expected down or up")),
+            };
+            events.push(KeyEvent {
+                frame,
+                key,
+                pressed,
+            });
+        }
+        Ok(KeyScript::new(events))
+    }
+
+    pub fn events(&self) -> &[KeyEvent] {
+        &self.events
+    }
+
+    /// Applies every event scheduled for `frame`.
+    pub fn apply(&self, frame: u64, chip8: &mut Chip8) {
+        let start = self.events.partition_point(|e| e.frame < frame);
+        for event in self.events[start..].iter().take_while(|e| e.frame == frame) {
+            chip8.set_key(event.key, event.pressed);
+        }
+    }
+}
+
+/// What a headless run got through before stopping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunReport {
+    pub frames: u64,
+    pub instructions: u64,
+    pub error: Option<ExecError>,
+}
+
+/// Runs `chip8` until `limit` is reached, it faults or it exits. Timers tick every
+/// `instructions_per_frame` instructions, as they would in a frontend.
+pub fn run(
+    chip8: &mut Chip8,
+    limit: RunLimit,
+    instructions_per_frame: u32,
+    script: &KeyScript,
+) -> RunReport {
+    run_with(chip8, limit, instructions_per_frame, script, None)
+}
+
+/// Like [`run`], handing the trace records `chip8` makes to `sink` at the
+/// end of every frame. Tracing must be switched on with
+/// [`Chip8::set_trace`].
+pub fn run_traced(
+    chip8: &mut Chip8,
+    limit: RunLimit,
+    instructions_per_frame: u32,
+    script: &KeyScript,
+    sink: &mut dyn TraceSink,
+) -> RunReport {
+    run_with(chip8, limit, instructions_per_frame, script, Some(sink))
+}
+
+fn run_with(
+    chip8: &mut Chip8,
+    limit: RunLimit,
+    instructions_per_frame: u32,
+    script: &KeyScript,
+    mut sink: Option<&mut dyn TraceSink>,
+) -> RunReport {
+    let mut drain = |chip8: &mut Chip8| {
+        if let Some(sink) = sink.as_mut() {
+            chip8
+                .take_trace()
+                .iter()
+                .for_each(|record| sink.record(record));
+        }
+    };
+    let ipf = instructions_per_frame.max(1) as u64;
+    let total = match limit {
+        RunLimit::Frames(frames) => frames.saturating_mul(ipf),
+        RunLimit::Instructions(instructions) => instructions,
+    };
+    let mut report = RunReport {
+        frames: 0,
+        instructions: 0,
+        error: None,
+    };
+    while report.instructions < total {
+        if report.instructions.is_multiple_of(ipf) {
+            script.apply(report.frames, chip8);
+        }
+        if let Err(err) = chip8.cycle() {
+            report.error = Some(err);
+            break;
+        }
+        report.instructions += 1;
+        if chip8.exited() {
+            break;
+        }
+        if report.instructions.is_multiple_of(ipf) {
+            chip8.tick_timers();
+            report.frames += 1;
+            drain(chip8);
+        }
+    }
+    drain(chip8);
+    report
+}
+
+/// Renders a framebuffer as text, `#` for lit pixels and `.` for unlit.
+pub fn framebuffer_ascii(video: &[u32], width: usize) -> String {
+    let mut out = String::with_capacity(video.len() + video.len() / width);
+    for row in video.chunks(width) {
+        out.extend(row.iter().map(|&p| if p != 0 { '#' } else { '.' }));
+        out.push('\n');
+    }
+    out
+}
+
+/// 64-bit FNV-1a, used to fingerprint memory so runs can be compared.
+pub fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_script_test() {
+        let script = KeyScript::parse("# comment\n10 a down\n\n5 0x5 down # press\n12 A up\n")
+            .expect("should parse");
+        assert_eq!(script.events().len(), 3);
+        assert_eq!(script.events()[0].frame, 5);
+
+        let mut chip8 = Chip8::new();
+        script.apply(10, &mut chip8);
+        assert_eq!(chip8.keypad()[0xA], 1);
+        assert_eq!(chip8.keypad()[0x5], 0);
+
+        assert!(KeyScript::parse("1 10 down").is_err());
+        assert!(KeyScript::parse("1 1 sideways").is_err());
+        assert!(KeyScript::parse("x 1 down").is_err());
+    }
+
+    #[test]
+    fn run_test() {
+        // LD V0, 0x05; LD ST, V0; SKP V1 (key 0); JP 0x204; LD V2, 0x01; JP 0x20A
+        let rom = [
+            0x60, 0x05, 0xF0, 0x18, 0xE1, 0x9E, 0x12, 0x04, 0x62, 0x01, 0x12, 0x0A,
+        ];
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&rom).unwrap();
+        let script = KeyScript::parse("3 0 down").unwrap();
+        let report = run(&mut chip8, RunLimit::Frames(5), 10, &script);
+        assert_eq!(report.frames, 5);
+        assert_eq!(report.instructions, 50);
+        assert_eq!(report.error, None);
+        assert_eq!(chip8.registers()[2], 1);
+        assert_eq!(chip8.sound_timer(), 0);
+
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x00, 0xEE]).unwrap();
+        let report = run(&mut chip8, RunLimit::Instructions(10), 10, &script);
+        assert_eq!(report.instructions, 0);
+        assert_eq!(
+            report.error,
+            Some(ExecError::StackUnderflow { addr: 0x200 })
+        );
+    }
+
+    #[test]
+    fn run_traced_test() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        chip8.set_trace(Some(crate::TraceFilter {
+            range: None,
+            classes: 1 << 7,
+        }));
+        let mut records = Vec::new();
+        let script = KeyScript::default();
+        run_traced(
+            &mut chip8,
+            RunLimit::Instructions(9),
+            4,
+            &script,
+            &mut records,
+        );
+        assert_eq!(records.len(), 5);
+        assert_eq!(records[4].changes, [(0, 4, 5)]);
+        assert!(chip8.take_trace().is_empty());
+    }
+
+    #[test]
+    fn framebuffer_ascii_test() {
+        let ascii = framebuffer_ascii(&[1, 0, 0, 1], 2);
+        assert_eq!(ascii, "#.\n.#\n");
+        assert_ne!(fnv1a(b"a"), fnv1a(b"b"));
+    }
+}
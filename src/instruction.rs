@@ -0,0 +1,437 @@
+//! Typed CHIP-8 instructions. [`Instruction::decode`] parses an opcode
+//! once, [`crate::Chip8`] executes the result, and `Display` gives the
+//! mnemonic the debugger, disassembler, tracer and assembler all share.
+
+use crate::chip8::Mode;
+use std::error::Error;
+use std::fmt;
+
+/// An opcode that is not an instruction on any platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "illegal opcode {:04X}", self.opcode)
+    }
+}
+
+impl Error for DecodeError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0N00: call to the machine code routine at N00, ignored. Other 0NNN
+    /// opcodes are the instructions below or illegal.
+    Sys(u16),
+    /// 00CN (SUPER-CHIP)
+    ScrollDown(u8),
+    /// 00DN (XO-CHIP)
+    ScrollUp(u8),
+    /// 00E0
+    Clear,
+    /// 00EE
+    Return,
+    /// 00FB (SUPER-CHIP)
+    ScrollRight,
+    /// 00FC (SUPER-CHIP)
+    ScrollLeft,
+    /// 00FD (SUPER-CHIP)
+    Exit,
+    /// 00FE (SUPER-CHIP)
+    Lores,
+    /// 00FF (SUPER-CHIP)
+    Hires,
+    /// 1NNN
+    Jump(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN
+    SkipEqImm { x: u8, nn: u8 },
+    /// 4XNN
+    SkipNeImm { x: u8, nn: u8 },
+    /// 5XY0
+    SkipEq { x: u8, y: u8 },
+    /// 5XY2 (XO-CHIP)
+    StoreRange { x: u8, y: u8 },
+    /// 5XY3 (XO-CHIP)
+    LoadRange { x: u8, y: u8 },
+    /// 6XNN
+    LoadImm { x: u8, nn: u8 },
+    /// 7XNN
+    AddImm { x: u8, nn: u8 },
+    /// 8XY0
+    Move { x: u8, y: u8 },
+    /// 8XY1
+    Or { x: u8, y: u8 },
+    /// 8XY2
+    And { x: u8, y: u8 },
+    /// 8XY3
+    Xor { x: u8, y: u8 },
+    /// 8XY4
+    Add { x: u8, y: u8 },
+    /// 8XY5
+    Sub { x: u8, y: u8 },
+    /// 8XY6
+    ShiftRight { x: u8, y: u8 },
+    /// 8XY7
+    SubN { x: u8, y: u8 },
+    /// 8XYE
+    ShiftLeft { x: u8, y: u8 },
+    /// 9XY0
+    SkipNe { x: u8, y: u8 },
+    /// ANNN
+    LoadI(u16),
+    /// BNNN
+    JumpV0(u16),
+    /// CXNN
+    Random { x: u8, nn: u8 },
+    /// DXYN
+    Draw { x: u8, y: u8, n: u8 },
+    /// EX9E
+    SkipKey(u8),
+    /// EXA1
+    SkipNotKey(u8),
+    /// F000 NNNN (XO-CHIP): I := the word following the opcode.
+    LoadLong,
+    /// FN01 (XO-CHIP)
+    Plane(u8),
+    /// F002 (XO-CHIP)
+    Audio,
+    /// FX07
+    GetDelay(u8),
+    /// FX0A
+    WaitKey(u8),
+    /// FX15
+    SetDelay(u8),
+    /// FX18
+    SetSound(u8),
+    /// FX1E
+    AddI(u8),
+    /// FX29
+    Font(u8),
+    /// FX30 (SUPER-CHIP)
+    BigFont(u8),
+    /// FX33
+    Bcd(u8),
+    /// FX3A (XO-CHIP)
+    Pitch(u8),
+    /// FX55
+    Store(u8),
+    /// FX65
+    Load(u8),
+    /// FX75 (SUPER-CHIP)
+    SaveFlags(u8),
+    /// FX85 (SUPER-CHIP)
+    LoadFlags(u8),
+}
+
+impl Instruction {
+    /// Parses an opcode. Whether the instruction exists on the platform
+    /// being emulated is a separate question; see [`Instruction::platform`].
+    pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+        use Instruction::*;
+        let x = ((opcode >> 8) & 0xF) as u8;
+        let y = ((opcode >> 4) & 0xF) as u8;
+        let n = (opcode & 0xF) as u8;
+        let nn = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+        let illegal = Err(DecodeError { opcode });
+        let instruction = match opcode >> 12 {
+            0x0 => match (x, nn) {
+                (_, 0x00) => Sys(nnn),
+                (0, 0xC0..=0xCF) => ScrollDown(n),
+                (0, 0xD0..=0xDF) => ScrollUp(n),
+                (0, 0xE0) => Clear,
+                (0, 0xEE) => Return,
+                (0, 0xFB) => ScrollRight,
+                (0, 0xFC) => ScrollLeft,
+                (0, 0xFD) => Exit,
+                (0, 0xFE) => Lores,
+                (0, 0xFF) => Hires,
+                _ => return illegal,
+            },
+            0x1 => Jump(nnn),
+            0x2 => Call(nnn),
+            0x3 => SkipEqImm { x, nn },
+            0x4 => SkipNeImm { x, nn },
+            0x5 => match n {
+                0x0 => SkipEq { x, y },
+                0x2 => StoreRange { x, y },
+                0x3 => LoadRange { x, y },
+                _ => return illegal,
+            },
+            0x6 => LoadImm { x, nn },
+            0x7 => AddImm { x, nn },
+            0x8 => match n {
+                0x0 => Move { x, y },
+                0x1 => Or { x, y },
+                0x2 => And { x, y },
+                0x3 => Xor { x, y },
+                0x4 => Add { x, y },
+                0x5 => Sub { x, y },
+                0x6 => ShiftRight { x, y },
+                0x7 => SubN { x, y },
+                0xE => ShiftLeft { x, y },
+                _ => return illegal,
+            },
+            0x9 if n == 0 => SkipNe { x, y },
+            0xA => LoadI(nnn),
+            0xB => JumpV0(nnn),
+            0xC => Random { x, nn },
+            0xD => Draw { x, y, n },
+            0xE if nn == 0x9E => SkipKey(x),
+            0xE if nn == 0xA1 => SkipNotKey(x),
+            0xF => match nn {
+                0x00 if x == 0 => LoadLong,
+                0x01 => Plane(x),
+                0x02 if x == 0 => Audio,
+                0x07 => GetDelay(x),
+                0x0A => WaitKey(x),
+                0x15 => SetDelay(x),
+                0x18 => SetSound(x),
+                0x1E => AddI(x),
+                0x29 => Font(x),
+                0x30 => BigFont(x),
+                0x33 => Bcd(x),
+                0x3A => Pitch(x),
+                0x55 => Store(x),
+                0x65 => Load(x),
+                0x75 => SaveFlags(x),
+                0x85 => LoadFlags(x),
+                _ => return illegal,
+            },
+            _ => return illegal,
+        };
+        Ok(instruction)
+    }
+
+    /// The opcode this instruction decodes from.
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+        let xy = |x: u8, y: u8| (x as u16) << 8 | (y as u16) << 4;
+        let xnn = |x: u8, nn: u8| (x as u16) << 8 | nn as u16;
+        let x_ = |x: u8| (x as u16) << 8;
+        match *self {
+            Sys(nnn) => nnn,
+            ScrollDown(n) => 0x00C0 | n as u16,
+            ScrollUp(n) => 0x00D0 | n as u16,
+            Clear => 0x00E0,
+            Return => 0x00EE,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            Lores => 0x00FE,
+            Hires => 0x00FF,
+            Jump(nnn) => 0x1000 | nnn,
+            Call(nnn) => 0x2000 | nnn,
+            SkipEqImm { x, nn } => 0x3000 | xnn(x, nn),
+            SkipNeImm { x, nn } => 0x4000 | xnn(x, nn),
+            SkipEq { x, y } => 0x5000 | xy(x, y),
+            StoreRange { x, y } => 0x5002 | xy(x, y),
+            LoadRange { x, y } => 0x5003 | xy(x, y),
+            LoadImm { x, nn } => 0x6000 | xnn(x, nn),
+            AddImm { x, nn } => 0x7000 | xnn(x, nn),
+            Move { x, y } => 0x8000 | xy(x, y),
+            Or { x, y } => 0x8001 | xy(x, y),
+            And { x, y } => 0x8002 | xy(x, y),
+            Xor { x, y } => 0x8003 | xy(x, y),
+            Add { x, y } => 0x8004 | xy(x, y),
+            Sub { x, y } => 0x8005 | xy(x, y),
+            ShiftRight { x, y } => 0x8006 | xy(x, y),
+            SubN { x, y } => 0x8007 | xy(x, y),
+            ShiftLeft { x, y } => 0x800E | xy(x, y),
+            SkipNe { x, y } => 0x9000 | xy(x, y),
+            LoadI(nnn) => 0xA000 | nnn,
+            JumpV0(nnn) => 0xB000 | nnn,
+            Random { x, nn } => 0xC000 | xnn(x, nn),
+            Draw { x, y, n } => 0xD000 | xy(x, y) | n as u16,
+            SkipKey(x) => 0xE09E | x_(x),
+            SkipNotKey(x) => 0xE0A1 | x_(x),
+            LoadLong => 0xF000,
+            Plane(n) => 0xF001 | x_(n),
+            Audio => 0xF002,
+            GetDelay(x) => 0xF007 | x_(x),
+            WaitKey(x) => 0xF00A | x_(x),
+            SetDelay(x) => 0xF015 | x_(x),
+            SetSound(x) => 0xF018 | x_(x),
+            AddI(x) => 0xF01E | x_(x),
+            Font(x) => 0xF029 | x_(x),
+            BigFont(x) => 0xF030 | x_(x),
+            Bcd(x) => 0xF033 | x_(x),
+            Pitch(x) => 0xF03A | x_(x),
+            Store(x) => 0xF055 | x_(x),
+            Load(x) => 0xF065 | x_(x),
+            SaveFlags(x) => 0xF075 | x_(x),
+            LoadFlags(x) => 0xF085 | x_(x),
+        }
+    }
+
+    /// The earliest platform that has this instruction.
+    pub fn platform(&self) -> Mode {
+        use Instruction::*;
+        match self {
+            ScrollDown(_) | ScrollRight | ScrollLeft | Exit | Lores | Hires | BigFont(_)
+            | SaveFlags(_) | LoadFlags(_) => Mode::SuperChip,
+            ScrollUp(_)
+            | StoreRange { .. }
+            | LoadRange { .. }
+            | LoadLong
+            | Plane(_)
+            | Audio
+            | Pitch(_) => Mode::XoChip,
+            _ => Mode::Chip8,
+        }
+    }
+
+    /// Whether `mode` runs this instruction; each platform extends the last.
+    pub fn supported_in(&self, mode: Mode) -> bool {
+        match self.platform() {
+            Mode::Chip8 => true,
+            Mode::SuperChip => mode != Mode::Chip8,
+            Mode::XoChip => mode == Mode::XoChip,
+        }
+    }
+
+    /// Size in bytes, counting the address word after [`Instruction::LoadLong`].
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LoadLong => 4,
+            _ => 2,
+        }
+    }
+
+    /// The address operand of a jump, call or index load, if any.
+    pub fn target(&self) -> Option<u16> {
+        match *self {
+            Instruction::Jump(nnn)
+            | Instruction::Call(nnn)
+            | Instruction::LoadI(nnn)
+            | Instruction::JumpV0(nnn) => Some(nnn),
+            _ => None,
+        }
+    }
+
+    /// Formats the mnemonic with the address operand (see
+    /// [`Instruction::target`]) rendered by `addr`, e.g. as a label.
+    pub fn format_with(&self, addr: impl Fn(u16) -> String) -> String {
+        match *self {
+            Instruction::Jump(nnn) => format!("JMP {}", addr(nnn)),
+            Instruction::Call(nnn) => format!("CALL {}", addr(nnn)),
+            Instruction::LoadI(nnn) => format!("LD I, {}", addr(nnn)),
+            Instruction::JumpV0(nnn) => format!("JMP V0, {}", addr(nnn)),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+        match *self {
+            Sys(nnn) => write!(f, "SYS $0x{nnn:03X}"),
+            ScrollDown(n) => write!(f, "SCD ${n:01X}"),
+            ScrollUp(n) => write!(f, "SCU ${n:01X}"),
+            Clear => write!(f, "CLS"),
+            Return => write!(f, "RET"),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            Lores => write!(f, "LOW"),
+            Hires => write!(f, "HIGH"),
+            Jump(_) | Call(_) | LoadI(_) | JumpV0(_) => {
+                write!(f, "{}", self.format_with(|a| format!("$0x{a:03X}")))
+            }
+            SkipEqImm { x, nn } => write!(f, "SE V{x}, $0x{nn:03X}"),
+            SkipNeImm { x, nn } => write!(f, "SNE V{x}, $0x{nn:03X}"),
+            SkipEq { x, y } => write!(f, "SE V{x} V{y}"),
+            StoreRange { x, y } => write!(f, "LD [I], V{x}-V{y}"),
+            LoadRange { x, y } => write!(f, "LD V{x}-V{y}, [I]"),
+            LoadImm { x, nn } => write!(f, "LD V{x}, 0x{nn:03X}"),
+            AddImm { x, nn } => write!(f, "ADD V{x}, $0x{nn:03X}"),
+            Move { x, y } => write!(f, "LD V{x}, V{y}"),
+            Or { x, y } => write!(f, "OR V{x}, V{y}"),
+            And { x, y } => write!(f, "AND V{x}, V{y}"),
+            Xor { x, y } => write!(f, "XOR V{x}, V{y}"),
+            Add { x, y } => write!(f, "ADD V{x}, V{y}"),
+            Sub { x, y } => write!(f, "SUB V{x}, V{y}"),
+            ShiftRight { x, y } => write!(f, "SHR V{x}, V{y}"),
+            SubN { x, y } => write!(f, "SUBN V{x}, V{y}"),
+            ShiftLeft { x, y } => write!(f, "SHL V{x}, V{y}"),
+            SkipNe { x, y } => write!(f, "SNE V{x}, V{y}"),
+            Random { x, nn } => write!(f, "RND V{x}, $0x{nn:03X}"),
+            Draw { x, y, n } => write!(f, "DRW V{x}, V{y}, ${n:02X}"),
+            SkipKey(x) => write!(f, "SKP V{x}"),
+            SkipNotKey(x) => write!(f, "SKNP V{x}"),
+            LoadLong => write!(f, "LD I, long"),
+            Plane(n) => write!(f, "PLANE ${n:01X}"),
+            Audio => write!(f, "AUDIO"),
+            GetDelay(x) => write!(f, "LD V{x}, DT"),
+            WaitKey(x) => write!(f, "LD V{x}, K"),
+            SetDelay(x) => write!(f, "LD DT, V{x}"),
+            SetSound(x) => write!(f, "LD ST, V{x}"),
+            AddI(x) => write!(f, "ADD I, V{x}"),
+            Font(x) => write!(f, "LD F, V{x}"),
+            BigFont(x) => write!(f, "LD HF, V{x}"),
+            Bcd(x) => write!(f, "LD B, V{x}"),
+            Pitch(x) => write!(f, "PITCH V{x}"),
+            Store(x) => write!(f, "LD [I], V{x}"),
+            Load(x) => write!(f, "LD V{x}, [I]"),
+            SaveFlags(x) => write!(f, "LD R, V{x}"),
+            LoadFlags(x) => write!(f, "LD V{x}, R"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_test() {
+        assert_eq!(
+            Instruction::decode(0xD125),
+            Ok(Instruction::Draw { x: 1, y: 2, n: 5 })
+        );
+        assert_eq!(Instruction::decode(0x0000), Ok(Instruction::Sys(0)));
+        assert_eq!(
+            Instruction::decode(0x800F),
+            Err(DecodeError { opcode: 0x800F })
+        );
+        assert_eq!(
+            Instruction::decode(0x01E0),
+            Err(DecodeError { opcode: 0x01E0 })
+        );
+        assert_eq!(Instruction::decode(0xF000).unwrap().size(), 4);
+
+        let plane = Instruction::decode(0xF201).unwrap();
+        assert!(plane.supported_in(Mode::XoChip));
+        assert!(!plane.supported_in(Mode::SuperChip));
+        assert!(Instruction::Hires.supported_in(Mode::XoChip));
+        assert!(!Instruction::Hires.supported_in(Mode::Chip8));
+    }
+
+    #[test]
+    fn encode_test() {
+        // Every opcode that decodes encodes back to itself
+        for opcode in 0..=0xFFFF {
+            if let Ok(instruction) = Instruction::decode(opcode) {
+                assert_eq!(instruction.encode(), opcode, "{instruction}");
+            }
+        }
+    }
+
+    #[test]
+    fn display_test() {
+        let text = |opcode| Instruction::decode(opcode).unwrap().to_string();
+        assert_eq!(text(0x6A01), "LD V10, 0x001");
+        assert_eq!(text(0xD125), "DRW V1, V2, $05");
+        assert_eq!(text(0x2222), "CALL $0x222");
+        assert_eq!(text(0x5122), "LD [I], V1-V2");
+        assert_eq!(
+            Instruction::JumpV0(0x300).format_with(|a| format!("table_{a:03X}")),
+            "JMP V0, table_300"
+        );
+    }
+}
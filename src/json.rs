@@ -0,0 +1,243 @@
+//! JSON documents as [`Value`]s. Numbers without a fraction or exponent are
+//! integers; `null` members of objects are left out, as if absent.
+
+use crate::value::Value;
+use std::collections::BTreeMap;
+
+pub fn parse(src: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        bytes: src.as_bytes(),
+        pos: 0,
+        line: 1,
+    };
+    let value = parser
+        .value()?
+        .ok_or_else(|| parser.error("null is not allowed here"))?;
+    parser.skip_blank();
+    if parser.pos < parser.bytes.len() {
+        return Err(parser.error("trailing characters after the document"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> String {
+        format!("line {}: {msg}", self.line)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn skip_blank(&mut self) {
+        while let Some(b) = self.peek() {
+            match b {
+                b'\n' => self.line += 1,
+                b' ' | b'\t' | b'\r' => {}
+                _ => break,
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), String> {
+        self.skip_blank();
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("expected `{}`", expected as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// The next value, or `None` for `null`.
+    fn value(&mut self) -> Result<Option<Value>, String> {
+        self.skip_blank();
+        let value = match self.peek() {
+            Some(b'{') => self.object()?,
+            Some(b'[') => self.array()?,
+            Some(b'"') => Value::String(self.string()?),
+            Some(b'-' | b'0'..=b'9') => self.number()?,
+            Some(_) if self.keyword("true") => Value::Bool(true),
+            Some(_) if self.keyword("false") => Value::Bool(false),
+            Some(_) if self.keyword("null") => return Ok(None),
+            Some(_) => return Err(self.error("expected a value")),
+            None => return Err(self.error("unexpected end of document")),
+        };
+        Ok(Some(value))
+    }
+
+    fn keyword(&mut self, word: &str) -> bool {
+        let found = self.bytes[self.pos..].starts_with(word.as_bytes());
+        if found {
+            self.pos += word.len();
+        }
+        found
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut table = BTreeMap::new();
+        self.skip_blank();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Table(table));
+        }
+        loop {
+            self.skip_blank();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a member name"));
+            }
+            let key = self.string()?;
+            self.expect(b':')?;
+            if let Some(value) = self.value()? {
+                table.insert(key, value);
+            }
+            self.skip_blank();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Table(table));
+                }
+                _ => return Err(self.error("expected `,` or `}` in object")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_blank();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            let item = self
+                .value()?
+                .ok_or_else(|| self.error("null is not allowed in arrays"))?;
+            items.push(item);
+            self.skip_blank();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error("expected `,` or `]` in array")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let Some(b) = self.peek() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\n' => return Err(self.error("unterminated string")),
+                b'\\' => {
+                    let escaped = match self.peek() {
+                        Some(b'n') => '\n',
+                        Some(b't') => '\t',
+                        Some(b'r') => '\r',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(c @ (b'"' | b'\\' | b'/')) => c as char,
+                        Some(b'u') => {
+                            let hex = self
+                                .bytes
+                                .get(self.pos + 1..self.pos + 5)
+                                .and_then(|hex| std::str::from_utf8(hex).ok())
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+                            self.pos += 4;
+                            // Surrogate pairs come out as the replacement character
+                            hex.map(|c| char::from_u32(c).unwrap_or('\u{fffd}'))
+                                .ok_or_else(|| self.error("invalid unicode escape"))?
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    self.pos += 1;
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(escaped.encode_utf8(&mut buf).as_bytes());
+                }
+                _ => out.push(b),
+            }
+        }
+        String::from_utf8(out).map_err(|_| self.error("invalid UTF-8 in string"))
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|b| b.is_ascii_digit() || b"+-.eE".contains(&b))
+        {
+            self.pos += 1;
+        }
+        let token = std::str::from_utf8(&self.bytes[start..self.pos]).expect("ASCII");
+        let value = if token.contains(['.', 'e', 'E']) {
+            token.parse().ok().map(Value::Float)
+        } else {
+            token.parse().ok().map(Value::Integer)
+        };
+        value.ok_or_else(|| self.error(&format!("invalid number `{token}`")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let doc = parse(
+            r##"{
+  "title": "Bréakout \"2\"",
+  "tickrate": 30,
+  "volume": 2.5e-1,
+  "colors": { "pixels": ["#000000", "#ffffff"] },
+  "release": null,
+  "flags": [true, false, {}]
+}"##,
+        )
+        .unwrap();
+        assert_eq!(
+            doc.get("title").and_then(Value::as_str),
+            Some("Bréakout \"2\"")
+        );
+        assert_eq!(doc.get("tickrate"), Some(&Value::Integer(30)));
+        assert_eq!(doc.get("volume"), Some(&Value::Float(0.25)));
+        assert_eq!(
+            doc.get("colors")
+                .and_then(|c| c.get("pixels"))
+                .unwrap()
+                .to_string(),
+            "#000000,#ffffff"
+        );
+        assert_eq!(doc.get("release"), None);
+        assert_eq!(doc.get("flags").and_then(Value::as_array).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn errors_test() {
+        assert_eq!(
+            parse("{\n  \"a\": 1,\n  \"b\" 2\n}"),
+            Err("line 3: expected `:`".to_string())
+        );
+        assert!(parse("[1, null]").is_err());
+        assert!(parse("{} {}").is_err());
+        assert!(parse("\"open").is_err());
+        assert!(parse("").is_err());
+    }
+}
@@ -0,0 +1,261 @@
+//! Keyboard layouts: which physical keys press which CHIP-8 keys.
+//!
+//! Physical keys are named the way SDL names scancodes (`"Q"`, `"1"`,
+//! `"Up"`, `"Keypad 8"`), compared case-insensitively. Scancodes are key
+//! positions, so the names are those of a US layout whatever layout is
+//! active. Controller inputs are named as in [`crate::gamepad`]. Keymap
+//! files look like:
+//!
+//! ```text
+//! # CHIP-8 key = physical keys
+//! 5 = W, Up
+//! 8 = S, Down
+//!
+//! [pong.ch8]
+//! 1 = Left Shift
+//! ```
+//!
+//! Lines before any section apply to every ROM; a `[rom]` section then
+//! overrides them for ROMs with that file name. A listed key replaces all
+//! of its default bindings; unlisted keys keep them.
+
+use std::collections::BTreeMap;
+
+/// The standard 1234/QWER/ASDF/ZXCV layout, indexed by CHIP-8 key.
+const DEFAULT_LAYOUT: [&str; 16] = [
+    "X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V",
+];
+
+/// Default controller bindings: the d-pad and left stick as the WASD keys,
+/// A and B as E and Q.
+const DEFAULT_PAD_LAYOUT: [(u8, &str); 10] = [
+    (0x5, "Pad dpup"),
+    (0x5, "Pad lefty-"),
+    (0x7, "Pad dpleft"),
+    (0x7, "Pad leftx-"),
+    (0x8, "Pad dpdown"),
+    (0x8, "Pad lefty+"),
+    (0x9, "Pad dpright"),
+    (0x9, "Pad leftx+"),
+    (0x6, "Pad a"),
+    (0x4, "Pad b"),
+];
+
+/// The controller inputs that follow each ROM database key hint (see
+/// [`crate::romdb::KEY_HINTS`]).
+const HINT_INPUTS: [(&str, &[&str]); 6] = [
+    ("up", &["Pad dpup", "Pad lefty-"]),
+    ("down", &["Pad dpdown", "Pad lefty+"]),
+    ("left", &["Pad dpleft", "Pad leftx-"]),
+    ("right", &["Pad dpright", "Pad leftx+"]),
+    ("a", &["Pad a"]),
+    ("b", &["Pad b"]),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    keys: [Vec<String>; 16],
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keys = DEFAULT_LAYOUT.map(|name| vec![name.to_string()]);
+        for (key, name) in DEFAULT_PAD_LAYOUT {
+            keys[key as usize].push(name.to_string());
+        }
+        Keymap { keys }
+    }
+}
+
+impl Keymap {
+    /// The CHIP-8 key `physical` is bound to.
+    pub fn lookup(&self, physical: &str) -> Option<u8> {
+        self.keys
+            .iter()
+            .position(|names| names.iter().any(|n| n.eq_ignore_ascii_case(physical)))
+            .map(|key| key as u8)
+    }
+
+    /// The physical keys bound to `key`.
+    pub fn physical(&self, key: u8) -> &[String] {
+        &self.keys[key as usize & 0xF]
+    }
+
+    /// Replaces the physical keys bound to `key`. Any of them bound to
+    /// another key are moved.
+    pub fn set(&mut self, key: u8, physical: Vec<String>) {
+        for names in &mut self.keys {
+            names.retain(|n| !physical.iter().any(|p| p.eq_ignore_ascii_case(n)));
+        }
+        self.keys[key as usize & 0xF] = physical;
+    }
+
+    /// Moves the controller inputs for each hint, such as `("up", 0x2)`,
+    /// to the key the hint gives. The keyboard is left alone.
+    pub fn apply_hints(&mut self, hints: &[(String, u8)]) {
+        for (hint, key) in hints {
+            let Some((_, inputs)) = HINT_INPUTS.iter().find(|(name, _)| name == hint) else {
+                continue;
+            };
+            for names in &mut self.keys {
+                names.retain(|n| !inputs.contains(&n.as_str()));
+            }
+            let names = &mut self.keys[*key as usize & 0xF];
+            names.extend(inputs.iter().map(|input| input.to_string()));
+        }
+    }
+
+    /// Makes `physical` the only key bound to `key`.
+    pub fn bind(&mut self, key: u8, physical: &str) {
+        self.set(key, vec![physical.to_string()]);
+    }
+}
+
+/// Keys set by one part of a keymap file.
+type Overrides = BTreeMap<u8, Vec<String>>;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeymapFile {
+    global: Overrides,
+    roms: BTreeMap<String, Overrides>,
+}
+
+impl KeymapFile {
+    pub fn parse(src: &str) -> Result<KeymapFile, String> {
+        let mut file = KeymapFile::default();
+        let mut section: Option<String> = None;
+        for (i, line) in src.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |msg: &str| format!("line {}: {msg}: {line}", i + 1);
+            if let Some(rom) = line.strip_prefix('[') {
+                let rom = rom
+                    .strip_suffix(']')
+                    .ok_or_else(|| err("unclosed section"))?;
+                section = Some(rom.trim().to_string());
+                continue;
+            }
+            let (key, physical) = line
+                .split_once('=')
+                .ok_or_else(|| err("expected `key = physical keys`"))?;
+            let key = u8::from_str_radix(key.trim().trim_start_matches("0x"), 16)
+                .ok()
+                .filter(|&k| k < 16)
+                .ok_or_else(|| err("invalid key"))?;
+            let physical: Vec<String> = physical
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect();
+            let overrides = match &section {
+                Some(rom) => file.roms.entry(rom.clone()).or_default(),
+                None => &mut file.global,
+            };
+            overrides.insert(key, physical);
+        }
+        Ok(file)
+    }
+
+    /// The keymap for the ROM with file name `rom`, or for any ROM.
+    pub fn keymap(&self, rom: Option<&str>) -> Keymap {
+        self.keymap_with_hints(rom, &[])
+    }
+
+    /// Like [`KeymapFile::keymap`], with the ROM database's key hints
+    /// applied to the defaults before the file's bindings.
+    pub fn keymap_with_hints(&self, rom: Option<&str>, hints: &[(String, u8)]) -> Keymap {
+        let mut keymap = Keymap::default();
+        keymap.apply_hints(hints);
+        let rom_overrides = rom.and_then(|rom| self.roms.get(rom));
+        for overrides in [Some(&self.global), rom_overrides].into_iter().flatten() {
+            for (&key, physical) in overrides {
+                keymap.set(key, physical.clone());
+            }
+        }
+        keymap
+    }
+
+    /// Stores all of `keymap` as the overrides for `rom`.
+    pub fn set_rom(&mut self, rom: &str, keymap: &Keymap) {
+        let overrides = (0..16).map(|key| (key, keymap.physical(key).to_vec()));
+        self.roms.insert(rom.to_string(), overrides.collect());
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::from("# CHIP-8 key = physical keys\n");
+        let write = |out: &mut String, overrides: &Overrides| {
+            for (key, physical) in overrides {
+                out.push_str(&format!("{key:X} = {}\n", physical.join(", ")));
+            }
+        };
+        write(&mut out, &self.global);
+        for (rom, overrides) in &self.roms {
+            out.push_str(&format!("\n[{rom}]\n"));
+            write(&mut out, overrides);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keymap_test() {
+        let mut keymap = Keymap::default();
+        assert_eq!(keymap.lookup("q"), Some(0x4));
+        assert_eq!(keymap.lookup("V"), Some(0xF));
+        assert_eq!(keymap.lookup("Up"), None);
+        assert_eq!(keymap.lookup("Pad dpup"), Some(0x5));
+
+        keymap.set(0x5, vec!["W".to_string(), "Up".to_string()]);
+        assert_eq!(keymap.lookup("up"), Some(0x5));
+        assert_eq!(keymap.lookup("W"), Some(0x5));
+
+        // Rebinding moves the key off its old one
+        keymap.bind(0x8, "Up");
+        assert_eq!(keymap.lookup("Up"), Some(0x8));
+        assert_eq!(keymap.physical(0x5), ["W"]);
+        assert!(keymap.lookup("S").is_none());
+    }
+
+    #[test]
+    fn file_test() {
+        let src = "# comment\n5 = W, Up\n\n[pong.ch8]\n1 = Left Shift\n0x5 = K\n";
+        let file = KeymapFile::parse(src).unwrap();
+
+        let global = file.keymap(None);
+        assert_eq!(global.lookup("Up"), Some(0x5));
+        assert_eq!(global.lookup("1"), Some(0x1));
+
+        let pong = file.keymap(Some("pong.ch8"));
+        assert_eq!(pong.lookup("left shift"), Some(0x1));
+        assert_eq!(pong.lookup("1"), None);
+        assert_eq!(pong.physical(0x5), ["K"]);
+        assert_eq!(file.keymap(Some("maze.ch8")), global);
+
+        let mut file = file;
+        let mut keymap = file.keymap(Some("maze.ch8"));
+        keymap.bind(0xA, "Space");
+        file.set_rom("maze.ch8", &keymap);
+        let reparsed = KeymapFile::parse(&file.render()).unwrap();
+        assert_eq!(reparsed, file);
+        assert_eq!(reparsed.keymap(Some("maze.ch8")).lookup("space"), Some(0xA));
+
+        // Hints move the controller but not the keyboard or the file's keys
+        let hints = [("up".to_string(), 0x2), ("a".to_string(), 0x3)];
+        let hinted = file.keymap_with_hints(None, &hints);
+        assert_eq!(hinted.lookup("Pad dpup"), Some(0x2));
+        assert_eq!(hinted.lookup("Pad lefty-"), Some(0x2));
+        assert_eq!(hinted.lookup("Pad a"), Some(0x3));
+        assert_eq!(hinted.lookup("Up"), Some(0x5));
+        assert_eq!(hinted.lookup("2"), Some(0x2));
+
+        assert!(KeymapFile::parse("G = Q").is_err());
+        assert!(KeymapFile::parse("[pong").is_err());
+        assert!(KeymapFile::parse("1 Q").is_err());
+    }
+}
@@ -0,0 +1,50 @@
+//! CHIP-8 interpreter core. Frontends (SDL, headless, tests) drive a
+//! [`Chip8`] by loading a ROM, calling [`Chip8::cycle`], feeding keys in
+//! with [`Chip8::set_key`] and reading the framebuffer back out.
+
+pub mod asm;
+pub mod audio;
+pub mod chip8;
+pub mod cli;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod gamepad;
+pub mod headless;
+pub mod instruction;
+pub mod json;
+pub mod keymap;
+pub mod movie;
+pub mod octo;
+pub mod palette;
+pub mod png;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod romdb;
+pub mod settings;
+pub mod sha1;
+pub mod state;
+pub mod toml;
+pub mod trace;
+pub mod value;
+pub mod watch;
+
+pub use audio::{AudioSettings, AudioSink, CaptureSink, SquareWave};
+pub use chip8::{
+    BIG_FONT, Chip8, DISPLAY_HEIGHT, DISPLAY_SIZE, DISPLAY_WIDTH, FONT, HIRES_HEIGHT, HIRES_WIDTH,
+    Mode, START_ADDRESS, TIMER_HZ,
+};
+pub use error::ExecError;
+pub use instruction::{DecodeError, Instruction};
+pub use keymap::{Keymap, KeymapFile};
+pub use movie::Movie;
+pub use palette::Palette;
+pub use quirks::Quirks;
+pub use random::Random;
+pub use rewind::Rewind;
+pub use romdb::{RomDb, RomInfo};
+pub use settings::Settings;
+pub use state::SaveState;
+pub use trace::{TraceFilter, TraceFormat, TraceRecord, TraceSink, TraceWriter};
+pub use watch::{Access, WatchHit, Watchpoint};
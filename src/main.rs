@@ -1,509 +1,165 @@
 extern crate sdl2;
-use rand::Rng;
+mod sdl;
+
+use chip8_emu::cli::{self, Config, OPTIONS_HELP, boot, configure};
+use chip8_emu::debugger::{self, Command, Debugger, StopReason};
+use chip8_emu::disasm::{self, LabelKind};
+use chip8_emu::headless::{self, KeyScript};
+use chip8_emu::{
+    AudioSink, Chip8, DISPLAY_HEIGHT, DISPLAY_WIDTH, KeymapFile, Mode, Movie, Palette, Rewind,
+    SaveState, TIMER_HZ, TraceSink, TraceWriter,
+};
+use chip8_emu::{RomDb, asm, octo, sha1};
+use sdl::audio::SdlBeeper;
+use sdl::gamepad::Gamepads;
 use sdl2::event::Event;
-use sdl2::keyboard::{Keycode, Scancode};
+use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Rect;
 use sdl2::render::{TextureCreator, WindowCanvas};
 use sdl2::video::{Window, WindowContext};
-use std::error::Error;
-use std::fs::File;
-use std::io::Read;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::Path;
 use std::process;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
 use std::time::{Duration, Instant};
-use std::{env, usize};
-
-const DISPLAY_WIDTH: usize = 64;
-const DISPLAY_HEIGHT: usize = 32;
-const DISPLAY_SIZE: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT;
-const PITCH: u32 = DISPLAY_WIDTH as u32 * 4;
-
-const START_ADDRESS: usize = 0x200;
-
-const FONT: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
-
-#[derive(Debug)]
-pub struct Config {
-    pub file_path: String,
-    pub video_scale_factor: u32,
-    pub cycle_delay: u32,
-}
 
-impl Config {
-    pub fn build(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
-        args.next();
+const USAGE: &str = "\
+usage: chip8-emu [run] ROM [OPTIONS]
+       chip8-emu test ROM [OPTIONS] [--expect HASH]
+       chip8-emu disasm ROM [--mode MODE]
+       chip8-emu asm SOURCE OUT
+       chip8-emu info ROM
 
-        let file_path = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didnt get a file path"),
-        };
+ROM is a binary image, or Octo source if it ends in .8o.
+";
 
-        let video_scale_factor: u32 = match args.next() {
-            Some(scale_str) => scale_str.parse().unwrap_or(2),
-            None => 2,
-        };
+const SETTINGS_HELP: &str = "\
+Defaults for these options (except the one-run ones: --headless to
+--play) are read from ~/.config/chip8-emu/config.toml, then chip8.toml in
+the working directory, each overridden by sections for the ROM's SHA-1:
 
-        let cycle_delay: u32 = match args.next() {
-            Some(delay) => delay.parse().unwrap_or(3),
-            None => 3,
-        };
+  scale = 4
+  [rom.<sha1>]
+  quirks = \"schip\"
 
-        Ok(Config {
-            file_path,
-            video_scale_factor,
-            cycle_delay,
-        })
-    }
+Known ROMs take their platform, quirks, speed and colours from a built-in
+database when nothing else sets them.
+
+Keys: Esc quits, M mutes, F5/F7 save/load the slot picked with F6,
+hold Backspace to rewind, F8 binds keys, F9 cycles palettes.";
+
+fn usage() -> String {
+    format!("{USAGE}\n{OPTIONS_HELP}\n\n{SETTINGS_HELP}")
 }
 
-#[derive(Debug)]
-struct Chip8 {
-    registers: [u8; 16],
-    memory: [u8; 4096],
-    index: u16,
-    pc: usize,
-    stack: [u16; 16],
-    sp: usize,
-    delay_timer: u8,
-    sound_timer: u8,
-    keypad: [u8; 16],
-    video: [u32; DISPLAY_SIZE],
+/// Quick-save slot `slot` lives next to the ROM as `<rom>.state<slot>`.
+fn slot_path(rom: &str, slot: u8) -> String {
+    format!("{rom}.state{slot}")
 }
 
-impl Chip8 {
-    pub fn new() -> Chip8 {
-        let mut chip8 = Chip8 {
-            registers: [0u8; 16],
-            memory: [0u8; 4096],
-            index: 0,
-            pc: START_ADDRESS,
-            stack: [0; 16],
-            sp: 0,
-            delay_timer: 0,
-            sound_timer: 0,
-            keypad: [0; 16],
-            video: [0; 64 * 32],
-        };
-        chip8.load_font();
-        chip8
-    }
+fn rom_name(rom: &str) -> &str {
+    Path::new(rom)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(rom)
+}
 
-    fn fetch(&mut self) -> u16 {
-        let msb = self.memory[self.pc as usize];
-        let lsb = self.memory[(self.pc + 1) as usize];
-        self.pc += 2;
-        // or the two bytes to make the instr
-        ((msb as u16) << 8) | (lsb as u16)
+fn load_keymap_file(path: &str) -> Result<KeymapFile, String> {
+    match fs::read_to_string(path) {
+        Ok(src) => KeymapFile::parse(&src).map_err(|e| format!("{path}: {e}")),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(KeymapFile::default()),
+        Err(e) => Err(format!("{path}: {e}")),
     }
+}
 
-    fn decode(&mut self, instr: u16) {
-        let opcode = instr & 0xF000;
-        let x = ((instr & 0x0F00) >> 8) as usize;
-        let y = ((instr & 0x00F0) >> 4) as usize;
-        let n = instr & 0x000F;
-        let nn = instr & 0x00FF;
-        let nnn = instr & 0x0FFF;
-        match opcode {
-            0x0000 => match nn {
-                0x00 => {
-                    println!("???? opcode={instr:04X}");
-                }
-                0xE0 => {
-                    println!("CLS");
-                    self.video = [0; 64 * 32]
-                }
-                0xEE => {
-                    println!("RET");
-                    println!(
-                        "RET: Restoring PC from stack[{}] = 0x{:03X}",
-                        self.sp - 1,
-                        self.stack[self.sp - 1]
-                    );
-                    self.sp -= 1;
-                    self.pc = self.stack[self.sp] as usize;
-                }
-                _ => panic!("Illegal instruction: {instr:04X}"),
-            },
+fn binding_prompt(key: u8) -> String {
+    format!("Chip8 Emulator - press a key or button for {key:X} (F8 skips)")
+}
 
-            0x1000 => {
-                println!("JMP $0x{nnn:03X}");
-                self.pc = nnn as usize;
-            }
-            0x2000 => {
-                println!("CALL $0x{nnn:03X}");
-                println!(
-                    "CALL 0x{:03X}: Saving PC=0x{:03X} to stack[{}]",
-                    nnn, self.pc, self.sp
-                );
-                self.stack[self.sp] = self.pc as u16;
-                self.sp += 1;
-                self.pc = nnn as usize;
-            }
-            0x3000 => {
-                println!("SE V{x}, $0x{nn:03X}");
-                if self.registers[x] as u16 == nn {
-                    self.pc += 2;
-                }
-            }
-            0x4000 => {
-                println!("SNE V{x}, $0x{nn:03X}");
-                if self.registers[x] as u16 != nn {
-                    self.pc += 2;
-                }
-            }
-            0x5000 => {
-                println!("SE V{x} V{y}");
-                if self.registers[x] == self.registers[y] {
-                    self.pc += 2;
-                }
-            }
-            0x6000 => {
-                println!("LD V{x}, 0x{nn:03X}");
-                self.registers[x] = nn as u8;
+/// Reads debugger commands from stdin on a background thread so the window
+/// keeps rendering while waiting for input.
+fn spawn_stdin_reader() -> Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if tx.send(line).is_err() {
+                break;
             }
-            0x7000 => {
-                // VX := VX + NN,
-                println!("ADD V{x}, $0x{nn:03X}");
-                let vx = self.registers[x];
-                let (result, _) = (vx as u16).overflowing_add(nn);
-                self.registers[x] = result as u8;
-            }
-            0x8000 => match n {
-                0x0 => {
-                    println!("LD V{x}, V{y}");
-                    self.registers[x] = self.registers[y];
-                }
-                0x1 => {
-                    // VX := VX | VY
-                    println!("OR V{x}, V{y}");
-                    self.registers[x] |= self.registers[y];
-                }
-                0x2 => {
-                    println!("AND V{x}, V{y}");
-                    self.registers[x] &= self.registers[y];
-                }
-                0x3 => {
-                    println!("XOR V{x}, V{y}");
-                    self.registers[x] ^= self.registers[y];
-                }
-                0x4 => {
-                    println!("ADD V{x}, V{y}");
-                    let vx = self.registers[x];
-                    let vy = self.registers[y];
-                    let (result, overflow) = vx.overflowing_add(vy);
-                    self.registers[0xF] = if overflow { 1 } else { 0 };
-                    self.registers[x] = result;
-                }
-                0x5 => {
-                    println!("SUB V{x}, V{y}");
-                    let vx = self.registers[x];
-                    let vy = self.registers[y];
-                    self.registers[0xF] = if vx > vy { 1 } else { 0 };
-                    let (result, _) = vx.overflowing_sub(vy);
-                    self.registers[x] = result;
-                }
-                0x6 => {
-                    println!("SHR V{x}, V{y}");
-                    self.registers[0xF] = self.registers[x] & 0x01;
-                    self.registers[x] /= 2;
-                }
-                0x7 => {
-                    println!("SUBN V{x}, V{y}");
-                    let vx = self.registers[x];
-                    let vy = self.registers[y];
-                    self.registers[0xF] = if vy > vx { 1 } else { 0 };
-                    let (result, _) = vy.overflowing_sub(vx);
-                    self.registers[x] = result;
-                }
-                0xE => {
-                    println!("SHL V{x}, V{y}");
-                    let vx = self.registers[x];
-                    self.registers[0xF] = vx & 0x80;
-                    let (r, _) = vx.overflowing_mul(2);
-                    self.registers[x] = r;
-                }
-                _ => panic!("Illegal instruction: {instr}"),
-            },
-            0x9000 => match n {
-                0x0 => {
-                    println!("SNE V{x}, V{y}");
-                    if self.registers[x] != self.registers[y] {
-                        self.pc += 2;
-                    }
-                }
-                _ => panic!("Illegal instruction {instr}"),
-            },
-            0xA000 => {
-                println!("LD I, $0x{nnn:03X}");
-                self.index = nnn;
-            }
-            0xB000 => {
-                println!("JMP V0, $0x{nnn:03X}");
-                self.pc = (self.registers[0x0] + (nnn as u8)) as usize;
-            }
-            0xC000 => {
-                println!("RND V{x}, $0x{nn:03X}");
-                let entropy = rand::rng().random_range(0..255) as u8;
-                self.registers[x] = entropy & nn as u8;
-            }
-            0xD000 => {
-                println!("DRW V{x}, V{y}, ${n:02X}");
-                let x_coord = self.registers[x] % (DISPLAY_WIDTH as u8);
-                let y_coord = self.registers[y] % (DISPLAY_HEIGHT as u8);
-
-                for row in 0..n {
-                    let addr = row + self.index;
-                    let bits = self.memory[addr as usize];
-                    let cy = (y_coord + row as u8) % (DISPLAY_HEIGHT as u8);
-                    for col in 0..8 {
-                        let cx = (x_coord + col) % (DISPLAY_WIDTH as u8);
-                        let sprite_pixel = bits & (0x80 >> col);
-
-                        let screen_pixel_loc = (cy as usize * DISPLAY_WIDTH) + cx as usize;
-                        let screen_pixel = self.video[screen_pixel_loc];
-                        if sprite_pixel > 0 {
-                            if screen_pixel > 0 {
-                                self.registers[0xF] = 1;
-                            }
-
-                            self.video[screen_pixel_loc] ^= 0xFFFFFFFF;
-                        }
-                    }
-                }
-            }
-            0xE000 => match nn {
-                0x9E => {
-                    println!("SKP V{x}");
-                    if self.keypad[self.registers[x] as usize] == 1 {
-                        self.pc += 2;
-                    }
-                }
-                0xA1 => {
-                    println!("SKNP V{x}");
-                    if self.keypad[self.registers[x] as usize] == 0 {
-                        self.pc += 2;
-                    }
-                }
-                _ => panic!("Illegal instruction: {instr}"),
-            },
-            0xF000 => match nn {
-                0x07 => {
-                    println!("LD V{x}, DT");
-                    self.registers[x] = self.delay_timer;
-                }
-                0x0A => {
-                    println!("LD V{x}, K");
-                    if self.keypad[0] == 1 {
-                        self.registers[x] = 0;
-                    } else if self.keypad[1] == 1 {
-                        self.registers[x] = 1;
-                    } else if self.keypad[2] == 1 {
-                        self.registers[x] = 2;
-                    } else if self.keypad[3] == 1 {
-                        self.registers[x] = 3;
-                    } else if self.keypad[4] == 1 {
-                        self.registers[x] = 4;
-                    } else if self.keypad[5] == 1 {
-                        self.registers[x] = 5;
-                    } else if self.keypad[6] == 1 {
-                        self.registers[x] = 6;
-                    } else if self.keypad[7] == 1 {
-                        self.registers[x] = 7;
-                    } else if self.keypad[8] == 1 {
-                        self.registers[x] = 8;
-                    } else if self.keypad[9] == 1 {
-                        self.registers[x] = 9;
-                    } else if self.keypad[10] == 1 {
-                        self.registers[x] = 10;
-                    } else if self.keypad[11] == 1 {
-                        self.registers[x] = 11;
-                    } else if self.keypad[12] == 1 {
-                        self.registers[x] = 12;
-                    } else if self.keypad[13] == 1 {
-                        self.registers[x] = 13;
-                    } else if self.keypad[14] == 1 {
-                        self.registers[x] = 14;
-                    } else if self.keypad[15] == 1 {
-                        self.registers[x] = 15;
-                    } else {
-                        println!("Waiting for keypress...");
-                        self.pc -= 2;
-                    }
-                }
-                0x15 => {
-                    println!("LD DT, V{x}");
-                    self.delay_timer = self.registers[x];
-                }
-                0x18 => {
-                    println!("LD ST, V{x}");
-                    self.sound_timer = self.registers[x];
-                }
-                0x1E => {
-                    println!("ADD I, V{x}");
-                    self.index += self.registers[x] as u16;
-                }
-                0x29 => {
-                    println!("LD F, V{x}");
-                    self.index = (self.registers[x] * 0x05 + 0x050) as u16;
-                }
-                0x33 => {
-                    println!("LD B, V{x}");
-                    let vx = self.registers[x];
-                    let h = vx / 100;
-                    let t = (vx - h * 100) / 10;
-                    let o = vx - h * 100 - t * 10;
-                    self.memory[self.index as usize] = h;
-                    self.memory[(self.index + 1) as usize] = t;
-                    self.memory[(self.index + 2) as usize] = o;
-                }
-                0x55 => {
-                    println!("LD [I], V{x}");
-                    for reg in 0..=x {
-                        self.memory[self.index as usize + reg] = self.registers[reg];
-                    }
-                }
-                0x65 => {
-                    println!("LD V{x}, [I]");
-                    for reg in 0..=x {
-                        self.registers[reg] = self.memory[(self.index as usize) + reg];
-                    }
-                }
-                _ => println!("Illegal instruction: {instr:03X}"),
-            },
-
-            _ => panic!("Illegal instruction: {instr:03X}"),
-        };
-    }
-
-    fn map_scancode_to_chip8_key(scancode: Scancode) -> Option<u8> {
-        match scancode {
-            Scancode::Num1 => Some(0x1),
-            Scancode::Num2 => Some(0x2),
-            Scancode::Num3 => Some(0x3),
-            Scancode::Num4 => Some(0xC),
-            Scancode::Q => Some(0x4),
-            Scancode::W => Some(0x5),
-            Scancode::E => Some(0x6),
-            Scancode::R => Some(0xD),
-            Scancode::A => Some(0x7),
-            Scancode::S => Some(0x8),
-            Scancode::D => Some(0x9),
-            Scancode::F => Some(0xE),
-            Scancode::Z => Some(0xA),
-            Scancode::X => Some(0x0),
-            Scancode::C => Some(0xB),
-            Scancode::V => Some(0xF),
-            _ => None,
-        }
-    }
-
-    fn load_font(&mut self) {
-        println!("[CHIP8] Loading font...");
-        // 050–09F
-        for (i, addr) in (0x050..0x09f + 1).enumerate() {
-            self.memory[addr] = FONT[i];
         }
-    }
-
-    pub fn cycle(&mut self) {
-        let instr = self.fetch();
-        self.decode(instr);
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
-        }
-
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1;
-        }
-    }
-
-    pub fn load_rom(&mut self, file_path: &String) -> Result<(), Box<dyn Error>> {
-        println!("[CHIP8] Loading ROM...");
-        let mut file = File::open(file_path)?;
-        let bytes_read = file.read(&mut self.memory[0x200..])?;
-        println!("[CHIP8] Loaded {bytes_read} bytes from {file_path}.");
-        Ok(())
-    }
+    });
+    rx
+}
 
-    #[allow(dead_code)]
-    pub fn memory_hexdump(&self, start: u16, end: u16) {
-        for (i, chunk) in self.memory[start as usize..end as usize]
-            .chunks(16)
-            .enumerate()
-        {
-            print!("0x{:03X}: ", (start as usize) + (i * 16));
-            for byte in chunk {
-                print!("{:02X} ", byte);
-            }
-            println!();
-        }
-    }
+fn prompt() {
+    print!("(chip8) ");
+    let _ = io::stdout().flush();
 }
 
 pub struct Renderer {
     canvas: WindowCanvas,
     texture_creator: TextureCreator<WindowContext>, // Store this!
+    palette: Palette,
 }
 
 impl Renderer {
-    pub fn new(window: Window) -> Result<Renderer, String> {
+    pub fn new(window: Window, palette: Palette) -> Result<Renderer, String> {
         let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
         let texture_creator = canvas.texture_creator();
 
         Ok(Renderer {
             canvas,
             texture_creator,
+            palette,
         })
     }
 
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn set_title(&mut self, title: &str) -> Result<(), String> {
+        self.canvas
+            .window_mut()
+            .set_title(title)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Draws a `width` x `height` framebuffer stretched over the whole
+    /// 64x32 * `scale` window, so hires output simply gets finer pixels.
     pub fn draw(
         &mut self,
-        framebuffer: &[u32; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+        framebuffer: &[u32],
+        width: usize,
+        height: usize,
         scale: u32,
     ) -> Result<(), String> {
         // RGBA
-        let mut pixels = [0u8; DISPLAY_HEIGHT * PITCH as usize];
+        let pitch = width * 4;
+        let mut pixels = vec![0u8; height * pitch];
         let mut texture = self
             .texture_creator
-            .create_texture_streaming(
-                PixelFormatEnum::RGBA8888,
-                DISPLAY_WIDTH as u32,
-                DISPLAY_HEIGHT as u32,
-            )
+            .create_texture_streaming(PixelFormatEnum::RGBA8888, width as u32, height as u32)
             .map_err(|e| e.to_string())?;
 
         for (i, &pixel) in framebuffer.iter().enumerate() {
-            let color = if pixel != 0 { 255 } else { 0 }; // White or black
+            let rgb = self.palette.colour(pixel);
             let pixel_start = i * 4;
-            pixels[pixel_start] = color; // R
-            pixels[pixel_start + 1] = color; // G
-            pixels[pixel_start + 2] = color; // B
-            pixels[pixel_start + 3] = color; // A
+            // RGBA8888 is a packed 0xRRGGBBAA in native byte order
+            pixels[pixel_start..pixel_start + 4].copy_from_slice(&(rgb << 8 | 0xFF).to_ne_bytes());
         }
 
         // Drawing logic here...
         // Update texture with pixel data
-        let _ = texture.update(None, &pixels, PITCH as usize);
+        let _ = texture.update(None, &pixels, pitch);
 
         // Clear canvas and draw texture scaled up
-        self.canvas.set_draw_color(sdl2::pixels::Color::BLACK);
+        let [_, r, g, b] = self.palette.colour(0).to_be_bytes();
+        self.canvas
+            .set_draw_color(sdl2::pixels::Color::RGB(r, g, b));
         self.canvas.clear();
 
         let dst_rect = Rect::new(
@@ -519,20 +175,187 @@ impl Renderer {
     }
 }
 
-fn main() -> Result<(), String> {
-    println!("[CHIP8] Start emulator");
+/// `disasm ROM [--mode MODE]`: prints a listing of the ROM.
+fn disassemble(args: &[String]) -> Result<(), String> {
+    let config = Config::build(args.iter().cloned())
+        .map_err(|e| format!("{e}\nusage: chip8-emu disasm ROM [--mode MODE]"))?;
+    let path = &config.file_path;
+    let rom = fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+    print!("{}", disasm::disassemble(&rom, config.mode).render());
+    Ok(())
+}
 
-    let config = Config::build(env::args()).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {err}");
-        process::exit(1);
-    });
+/// `asm SOURCE OUT`: assembles SOURCE into a ROM at OUT.
+fn assemble(args: &[String]) -> Result<(), String> {
+    let [source, out] = args else {
+        return Err("usage: chip8-emu asm SOURCE OUT".to_string());
+    };
+    let rom = asm::assemble_file(source)?;
+    fs::write(out, &rom).map_err(|e| format!("{out}: {e}"))?;
+    println!("{out}: {} bytes", rom.len());
+    Ok(())
+}
 
-    let mut chip8 = Chip8::new();
+/// `info ROM`: prints what can be worked out about a ROM without running it.
+fn info(args: &[String]) -> Result<(), String> {
+    let [path] = args else {
+        return Err("usage: chip8-emu info ROM".to_string());
+    };
+    let file = fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+    let mut rom = file.clone();
+    if path.ends_with(".8o") {
+        let src = String::from_utf8(rom).map_err(|e| format!("{path}: {e}"))?;
+        rom = octo::compile(&src).map_err(|e| format!("{path}:{e}"))?;
+    }
+    let listing = disasm::disassemble(&rom, Mode::XoChip);
+    let subroutines = listing
+        .labels()
+        .values()
+        .filter(|&&kind| kind == LabelKind::Sub)
+        .count();
+    println!("file:         {path}");
+    println!("size:         {} bytes", rom.len());
+    println!("hash:         {:016x}", headless::fnv1a(&rom));
+    let hash = sha1::hex_digest(&file);
+    println!("sha1:         {hash}");
+    if let Some(info) = RomDb::bundled().lookup(&hash) {
+        println!("title:        {}", info.byline());
+        if let Some(platform) = &info.platform {
+            println!("database:     {platform}, {}", info.args().join(" "));
+        }
+    }
+    println!("platform:     {:?}", listing.platform());
+    println!("instructions: {}", listing.code().len());
+    println!("subroutines:  {subroutines}");
+    Ok(())
+}
 
-    chip8.load_rom(&config.file_path).unwrap_or_else(|err| {
-        eprintln!("Problem loading ROM @ {}: {err}", &config.file_path);
+/// Boots the machine `config` describes, saying which ROM and seed it runs.
+fn boot_verbose(config: &Config) -> Result<(Chip8, Option<(KeyScript, Movie)>), String> {
+    println!("[CHIP8] Loading ROM...");
+    let (chip8, playback) = boot(config)?;
+    if let Some(info) = chip8.rom_info() {
+        println!("[CHIP8] {}", info.byline());
+    }
+    println!("[CHIP8] Random seed {}", chip8.seed());
+    Ok((chip8, playback))
+}
+
+/// Runs `config` headless and prints the final state, returning whether
+/// the run passed.
+fn run_headless(
+    config: &Config,
+    chip8: &mut Chip8,
+    playback: Option<(KeyScript, Movie)>,
+) -> Result<bool, String> {
+    let run = cli::run_headless(config, chip8, playback)?;
+    print!("{}", run.summary(chip8));
+    for failure in &run.failures {
+        eprintln!("{failure}");
+    }
+    Ok(run.failures.is_empty())
+}
+
+/// `test ROM [OPTIONS]`: a headless run that fails on a fault or a
+/// mismatched state hash.
+fn test(args: &[String]) -> Result<(), String> {
+    let mut config = configure(args)?;
+    config.headless = true;
+    config.check_headless()?;
+    let (mut chip8, playback) = boot_verbose(&config)?;
+    if !run_headless(&config, &mut chip8, playback)? {
+        println!("FAIL {}", config.file_path);
         process::exit(1);
-    });
+    }
+    println!("PASS {}", config.file_path);
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        eprintln!("{}", usage());
+        process::exit(2);
+    }
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", usage());
+        return;
+    }
+    let (command, rest) = match args[0].as_str() {
+        "run" | "test" | "disasm" | "asm" | "info" => (args[0].as_str(), &args[1..]),
+        _ => ("run", &args[..]),
+    };
+    let result = match command {
+        "test" => test(rest),
+        "disasm" => disassemble(rest),
+        "asm" => assemble(rest),
+        "info" => info(rest),
+        _ => match configure(rest) {
+            Ok(config) => run(config),
+            Err(err) => {
+                eprintln!("Problem parsing arguments: {err}\nRun with --help for usage.");
+                process::exit(2);
+            }
+        },
+    };
+    if let Err(err) = result {
+        eprintln!("{err}");
+        process::exit(1);
+    }
+}
+
+fn run(config: Config) -> Result<(), String> {
+    println!("[CHIP8] Start emulator");
+
+    if config.headless {
+        config.check_headless()?;
+    }
+    let (mut chip8, playback) = boot_verbose(&config)?;
+    if config.headless {
+        if !run_headless(&config, &mut chip8, playback)? {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let instructions_per_frame = match &playback {
+        Some((_, movie)) => movie.instructions_per_frame,
+        None => config.instructions_per_frame,
+    };
+    let mut recording = config
+        .record_path
+        .as_ref()
+        .map(|_| Movie::new(&chip8, instructions_per_frame));
+    // Rewinding or loading a state would desync a movie
+    let movie_active = playback.is_some() || recording.is_some();
+    let mut movie_frame = 0;
+    // Inputs held back while a recorded frame is stopped partway through
+    let mut held_inputs = Vec::new();
+
+    let mut keymap_file = load_keymap_file(&config.keymap_path)?;
+    let hints = chip8
+        .rom_info()
+        .map(|info| info.keys.clone())
+        .unwrap_or_default();
+    if !hints.is_empty() {
+        let shown: Vec<String> = hints
+            .iter()
+            .map(|(hint, key)| format!("{hint}={key:X}"))
+            .collect();
+        println!("[CHIP8] Key hints: {}", shown.join(" "));
+    }
+    let mut keymap = keymap_file.keymap_with_hints(Some(rom_name(&config.file_path)), &hints);
+    // The CHIP-8 key waiting for a physical key, while binding
+    let mut binding: Option<u8> = None;
+
+    let mut trace = match &config.trace_path {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| format!("{path}: {e}"))?;
+            chip8.set_trace(Some(config.trace_filter.clone()));
+            Some(TraceWriter::new(BufWriter::new(file), config.trace_format))
+        }
+        None => None,
+    };
 
     println!("[CHIP8] Init window");
 
@@ -550,16 +373,56 @@ fn main() -> Result<(), String> {
         .build()
         .map_err(|e| e.to_string())?;
 
-    let mut renderer = Renderer::new(window)?;
+    let mut palette = config.palette;
+    let mut renderer = Renderer::new(window, palette)?;
+    let mut beeper = SdlBeeper::new(&sdl_context.audio()?, config.audio)?;
 
+    let mut gamepads = Gamepads::new(sdl_context.game_controller()?, config.dead_zone);
     let mut event_pump = sdl_context.event_pump()?;
 
     println!("[CHIP8] Start fetch-decode-execute loop");
 
-    let mut last_cycle_time = Instant::now();
-    let cycle_delay = Duration::from_millis(config.cycle_delay as u64); // 2ms = 500Hz
+    let frame_duration = Duration::from_secs(1) / TIMER_HZ;
+    let mut next_frame = Instant::now();
+    let mut crashed = false;
+    let mut slot = 0;
+    let mut rewind = Rewind::new(
+        config.rewind_seconds,
+        config.rewind_mib.saturating_mul(1 << 20),
+    );
+    let mut rewinding = false;
+
+    let mut debugger = Debugger::new();
+    let commands = if config.debug {
+        println!("{}", debugger::HELP);
+        debugger.pause();
+        println!("{}", debugger.execute(Command::Regs, &mut chip8));
+        prompt();
+        Some(spawn_stdin_reader())
+    } else {
+        None
+    };
 
     'running: loop {
+        for line in commands.iter().flat_map(|rx| rx.try_iter()) {
+            if !line.trim().is_empty() {
+                match Command::parse(&line) {
+                    Ok(command) => {
+                        let output = debugger.execute(command, &mut chip8);
+                        if !output.is_empty() {
+                            println!("{output}");
+                        }
+                    }
+                    Err(err) => println!("{err}"),
+                }
+            }
+            if debugger.is_paused() {
+                prompt();
+            }
+        }
+
+        let prev_binding = binding;
+        let mut inputs = Vec::new();
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. }
@@ -568,71 +431,211 @@ fn main() -> Result<(), String> {
                     ..
                 } => break 'running,
                 Event::KeyDown {
-                    scancode: Some(scancode),
+                    keycode: Some(Keycode::M),
+                    ..
+                } => {
+                    let muted = beeper.toggle_mute();
+                    println!("[CHIP8] Sound {}", if muted { "muted" } else { "unmuted" });
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } if !movie_active => rewinding = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = false,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
                     ..
                 } => {
-                    // Map the scancode to a CHIP-8 key (0-F)
-                    if let Some(chip8_key_index) = Chip8::map_scancode_to_chip8_key(scancode) {
-                        // Update your CHIP-8's keypad state
-                        chip8.keypad[chip8_key_index as usize] = 1;
+                    let path = slot_path(&config.file_path, slot);
+                    match chip8.save_state().write_file(&path) {
+                        Ok(()) => println!("[CHIP8] Saved slot {slot}"),
+                        Err(err) => eprintln!("[CHIP8] Save failed: {err}"),
                     }
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    ..
+                } => {
+                    slot = (slot + 1) % 10;
+                    renderer.set_title(&format!("Chip8 Emulator - slot {slot}"))?;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    ..
+                } if !movie_active => {
+                    match SaveState::read_file(slot_path(&config.file_path, slot)) {
+                        Ok(state) => {
+                            chip8.load_state(state);
+                            crashed = false;
+                            println!("[CHIP8] Loaded slot {slot}");
+                        }
+                        Err(err) => eprintln!("[CHIP8] Load failed: {err}"),
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => {
+                    let (name, next) = palette.next_preset();
+                    palette = next;
+                    renderer.set_palette(palette);
+                    renderer.set_title(&format!("Chip8 Emulator - palette {name}"))?;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    ..
+                } => {
+                    // Starts binding at key 0, or skips to the next key
+                    let next = binding.map_or(0, |key| key + 1);
+                    binding = (next < 16).then_some(next);
+                }
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    ..
+                } => inputs.push((scancode.name().to_string(), true)),
                 Event::KeyUp {
                     scancode: Some(scancode),
                     ..
-                } => {
-                    // Map the scancode to a CHIP-8 key (0-F)
-                    if let Some(chip8_key_index) = Chip8::map_scancode_to_chip8_key(scancode) {
-                        // Update your CHIP-8's keypad state
-                        chip8.keypad[chip8_key_index as usize] = 0;
+                } => inputs.push((scancode.name().to_string(), false)),
+                other => inputs.extend(gamepads.handle(&other)),
+            }
+        }
+        // A movie only has keys for the start of each frame, so what is
+        // pressed while a recorded frame is stopped partway waits for the
+        // next one
+        if recording.is_some() && debugger.mid_frame() {
+            held_inputs.append(&mut inputs);
+        } else {
+            inputs.splice(0..0, held_inputs.drain(..));
+        }
+        // Keys and controller inputs go through the keymap alike
+        for (input, pressed) in inputs {
+            match binding {
+                Some(key) if pressed => {
+                    keymap.bind(key, &input);
+                    println!("[CHIP8] Bound {input} to {key:X}");
+                    binding = (key < 15).then_some(key + 1);
+                }
+                None if playback.is_none() => {
+                    if let Some(key) = keymap.lookup(&input) {
+                        chip8.set_key(key, pressed);
                     }
                 }
                 _ => {}
             }
         }
 
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 30));
+        let now = Instant::now();
+        if now < next_frame {
+            ::std::thread::sleep(next_frame - now);
+            continue;
+        }
+        // Don't try to catch up on frames lost while the window was stalled
+        next_frame = (next_frame + frame_duration).max(now);
+
+        if binding != prev_binding {
+            match binding {
+                Some(key) => renderer.set_title(&binding_prompt(key))?,
+                None => {
+                    keymap_file.set_rom(rom_name(&config.file_path), &keymap);
+                    fs::write(&config.keymap_path, keymap_file.render())
+                        .map_err(|e| format!("{}: {e}", config.keymap_path))?;
+                    println!("[CHIP8] Saved keymap to {}", config.keymap_path);
+                    renderer.set_title("Chip8 Emulator")?;
+                }
+            }
+        }
 
-        let current_time = Instant::now();
-        let dt = current_time.duration_since(last_cycle_time);
+        let running = binding.is_none() && !rewinding && !crashed && !debugger.is_paused();
+        // Finishing a frame the debugger stopped partway isn't a new one
+        if running && !debugger.mid_frame() {
+            if let Some((script, movie)) = &playback {
+                if movie_frame == movie.frames.len() as u64 {
+                    match movie.verify(&chip8) {
+                        Ok(()) => println!("[CHIP8] Movie finished, final state matches"),
+                        Err(err) => eprintln!("[CHIP8] Movie desynced: {err}"),
+                    }
+                    break 'running;
+                }
+                // Fed through set_key exactly like keyboard events
+                script.apply(movie_frame, &mut chip8);
+            }
+            if let Some(movie) = recording.as_mut() {
+                movie.record(chip8.keypad());
+            }
+            movie_frame += 1;
+        }
 
-        if dt >= cycle_delay {
-            last_cycle_time = current_time;
-            chip8.cycle();
-            let _ = renderer.draw(&chip8.video, config.video_scale_factor);
+        // Holding rewind steps back a frame each frame instead of running
+        let fault = if rewinding {
+            if rewind.step_back(&mut chip8) {
+                crashed = false;
+            }
+            None
+        } else if crashed || binding.is_some() {
+            None
+        } else if config.debug {
+            match debugger.run_frame(&mut chip8, instructions_per_frame) {
+                Some(reason) => {
+                    println!("{}", debugger.report(reason, &chip8));
+                    prompt();
+                    match reason {
+                        StopReason::Fault(err) => Some(err),
+                        _ => None,
+                    }
+                }
+                None => None,
+            }
+        } else {
+            chip8.run_frame(instructions_per_frame).err()
+        };
+        for line in debugger.take_log() {
+            println!("{line}");
+        }
+        if let Some(trace) = trace.as_mut() {
+            chip8
+                .take_trace()
+                .iter()
+                .for_each(|record| trace.record(record));
+        }
+        if running {
+            rewind.push(&chip8);
+        }
+        if let Some(err) = fault {
+            // Keep the window up with the last frame so the crash is visible
+            eprintln!("[CHIP8] Crashed: {err}");
+            renderer.set_title(&format!("Chip8 Emulator - crashed: {err}"))?;
+            crashed = true;
         }
-        // The rest of the game loop goes here...
+        if let Some(&pattern) = chip8.audio_pattern() {
+            beeper.set_pattern(pattern, chip8.playback_rate());
+        }
+        beeper.update(chip8.sound_active() && running);
+        if chip8.exited() {
+            break 'running;
+        }
+        let _ = renderer.draw(
+            chip8.video(),
+            chip8.display_width(),
+            chip8.display_height(),
+            config.video_scale_factor,
+        );
     }
-    println!("[CHIP8] Exiting...");
-    Ok(())
-}
-
-#[cfg(test)]
-mod tests {
-    #[test]
-
-    fn load_maze_rom_test() {
-        let fp = "maze.ch8".to_string();
-        let mut chip8 = super::Chip8::new();
-        chip8.load_rom(&fp).expect("should load the rom");
-        chip8.memory_hexdump(0x200, 0x238);
-        assert_eq!(chip8.memory[0x200], 0x60);
-        assert_eq!(chip8.memory[0x200], 0x60);
-        assert_eq!(chip8.memory[0x201], 0x00);
-        assert_eq!(chip8.memory[0x202], 0x61);
-        assert_eq!(chip8.memory[0x203], 0x00);
-        assert_eq!(chip8.memory[0x204], 0xA2);
-        assert_eq!(chip8.memory[0x205], 0x22);
-        assert_eq!(chip8.memory[0x210], 0x30);
-        assert_eq!(chip8.memory[0x220], 0x20);
-        assert_eq!(chip8.memory[0x221], 0x10);
-        assert_eq!(chip8.memory[0x230], 0x00);
+    if let (Some(path), Some(mut movie)) = (&config.record_path, recording) {
+        // A fault here happens in the replay too, at the same instruction
+        if !crashed {
+            let _ = debugger.finish_frame(&mut chip8, instructions_per_frame);
+        }
+        movie.finish(&chip8);
+        movie.write_file(path)?;
+        println!("[CHIP8] Recorded {} frames to {path}", movie.frames.len());
     }
-
-    #[test]
-    fn load_font_test() {
-        let mut chip8 = super::Chip8::new();
-        chip8.load_font();
-        assert_eq!(super::FONT, chip8.memory[0x050..0x09f + 1]);
+    if let Some(trace) = trace {
+        trace.finish().map_err(|e| format!("trace: {e}"))?;
     }
+    println!("[CHIP8] Exiting...");
+    Ok(())
 }
@@ -0,0 +1,287 @@
+//! Input movies: the keypad state at the start of every frame plus what the
+//! machine was started with, enough to replay a session exactly and check
+//! that it ended in the same state.
+//!
+//! The file is [`MAGIC`], a version byte, the ROM's SHA-1 (empty if it
+//! wasn't loaded from a file), the mode and quirk bits as in a save state,
+//! the generator name, the seed (u64), the [`state_hash`] at the start
+//! (u64), instructions per frame and frame count (u32), one u16 key bitmask
+//! per frame and the final [`state_hash`] (u64), all little-endian. Strings
+//! are a length byte and ASCII.
+
+use crate::chip8::{Chip8, Mode};
+use crate::headless::{KeyEvent, KeyScript, fnv1a};
+use crate::quirks::Quirks;
+use crate::state::{self, Reader};
+use std::fs;
+use std::path::Path;
+
+pub const MAGIC: [u8; 4] = *b"C8MV";
+pub const VERSION: u8 = 2;
+
+/// Fingerprints everything a save state captures.
+pub fn state_hash(chip8: &Chip8) -> u64 {
+    fnv1a(&chip8.save_state().encode())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    /// SHA-1 of the ROM file recorded on.
+    pub rom_sha1: Option<String>,
+    pub mode: Mode,
+    pub quirks: Quirks,
+    /// The CXNN generator's name.
+    pub rng: String,
+    pub seed: u64,
+    /// [`state_hash`] when recording started, which tells whether it
+    /// started from a save state.
+    pub start_hash: u64,
+    pub instructions_per_frame: u32,
+    /// Keys held at the start of each frame, bit N for key N.
+    pub frames: Vec<u16>,
+    /// [`state_hash`] after the last frame.
+    pub final_hash: u64,
+}
+
+impl Movie {
+    /// Starts recording `chip8` as it is now.
+    pub fn new(chip8: &Chip8, instructions_per_frame: u32) -> Movie {
+        Movie {
+            rom_sha1: chip8.rom_sha1().map(str::to_string),
+            mode: chip8.mode(),
+            quirks: *chip8.quirks(),
+            rng: chip8.rng_kind().to_string(),
+            seed: chip8.seed(),
+            start_hash: state_hash(chip8),
+            instructions_per_frame,
+            frames: Vec::new(),
+            final_hash: 0,
+        }
+    }
+
+    /// Records the keys for the frame about to run.
+    pub fn record(&mut self, keypad: &[u8; 16]) {
+        let keys = (0..16).fold(0, |bits, key| bits | ((keypad[key] != 0) as u16) << key);
+        self.frames.push(keys);
+    }
+
+    /// Ends the recording with the state `chip8` reached.
+    pub fn finish(&mut self, chip8: &Chip8) {
+        self.final_hash = state_hash(chip8);
+    }
+
+    /// The key presses and releases that reproduce the recorded keypad,
+    /// starting from every key up.
+    pub fn key_script(&self) -> KeyScript {
+        let mut events = Vec::new();
+        let mut held = 0;
+        for (frame, &keys) in self.frames.iter().enumerate() {
+            let changed = held ^ keys;
+            for key in (0..16).filter(|key| changed & (1 << key) != 0) {
+                events.push(KeyEvent {
+                    frame: frame as u64,
+                    key,
+                    pressed: keys & (1 << key) != 0,
+                });
+            }
+            held = keys;
+        }
+        KeyScript::new(events)
+    }
+
+    /// Checks that `chip8` is set up as it was when recording started. Call
+    /// before playing back, after seeding it and loading any save state.
+    pub fn check_start(&self, chip8: &Chip8) -> Result<(), String> {
+        let unknown = || "unknown".to_string();
+        if chip8.rom_sha1() != self.rom_sha1.as_deref() {
+            return Err(format!(
+                "recorded on ROM {}, not {}",
+                self.rom_sha1.clone().unwrap_or_else(unknown),
+                chip8.rom_sha1().map_or_else(unknown, str::to_string)
+            ));
+        }
+        if chip8.mode() != self.mode {
+            return Err(format!(
+                "recorded in {:?} mode, not {:?}",
+                self.mode,
+                chip8.mode()
+            ));
+        }
+        if *chip8.quirks() != self.quirks {
+            return Err(format!(
+                "recorded with quirks {}, not {}",
+                self.quirks.spec(),
+                chip8.quirks().spec()
+            ));
+        }
+        if chip8.rng_kind() != self.rng {
+            return Err(format!(
+                "recorded with the {} generator, not {}",
+                self.rng,
+                chip8.rng_kind()
+            ));
+        }
+        if state_hash(chip8) != self.start_hash {
+            return Err(
+                "starting state does not match the recording's; was it recorded from a \
+                 different save state?"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks that playback ended where the recording did.
+    pub fn verify(&self, chip8: &Chip8) -> Result<(), String> {
+        let hash = state_hash(chip8);
+        if hash != self.final_hash {
+            return Err(format!(
+                "state hash {hash:016x} does not match recorded {:016x}",
+                self.final_hash
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(96 + 2 * self.frames.len());
+        out.extend_from_slice(&MAGIC);
+        out.push(VERSION);
+        let rom_sha1 = self.rom_sha1.as_deref().unwrap_or_default();
+        out.push(rom_sha1.len() as u8);
+        out.extend_from_slice(rom_sha1.as_bytes());
+        out.push(state::mode_id(self.mode));
+        out.push(state::quirk_bits(self.quirks));
+        out.push(self.rng.len() as u8);
+        out.extend_from_slice(self.rng.as_bytes());
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        out.extend_from_slice(&self.start_hash.to_le_bytes());
+        out.extend_from_slice(&self.instructions_per_frame.to_le_bytes());
+        out.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for keys in &self.frames {
+            out.extend_from_slice(&keys.to_le_bytes());
+        }
+        out.extend_from_slice(&self.final_hash.to_le_bytes());
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Movie, String> {
+        let mut r = Reader::new(bytes, "movie");
+        if r.take(4)? != MAGIC {
+            return Err("not a movie".to_string());
+        }
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(format!("unsupported movie version {version}"));
+        }
+        let rom_sha1 = Some(r.string()?).filter(|hash| !hash.is_empty());
+        let mode = state::mode_from_id(r.u8()?)?;
+        let quirks = state::quirks_from_bits(r.u8()?);
+        let rng = r.string()?;
+        let seed = u64::from_le_bytes(r.array()?);
+        let start_hash = u64::from_le_bytes(r.array()?);
+        let instructions_per_frame = u32::from_le_bytes(r.array()?);
+        let count = u32::from_le_bytes(r.array()?) as usize;
+        let frames = r
+            .take(count * 2)?
+            .chunks(2)
+            .map(|keys| u16::from_le_bytes([keys[0], keys[1]]))
+            .collect();
+        let final_hash = u64::from_le_bytes(r.array()?);
+        r.finish()?;
+        Ok(Movie {
+            rom_sha1,
+            mode,
+            quirks,
+            rng,
+            seed,
+            start_hash,
+            instructions_per_frame,
+            frames,
+            final_hash,
+        })
+    }
+
+    pub fn write_file(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        fs::write(path, self.encode()).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn read_file(path: impl AsRef<Path>) -> Result<Movie, String> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Movie::decode(&bytes).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{self, RunLimit};
+
+    // RND V0, 0xFF; SKP V1 (key 0); JP 0x200; ADD V2, 1; JP 0x200
+    const ROM: [u8; 10] = [0xC0, 0xFF, 0xE1, 0x9E, 0x12, 0x00, 0x72, 0x01, 0x12, 0x00];
+
+    fn record(seed: u64) -> Movie {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&ROM).unwrap();
+        chip8.set_seed(seed);
+        let mut movie = Movie::new(&chip8, 9);
+        for frame in 0..12 {
+            chip8.set_key(0, (3..7).contains(&frame));
+            chip8.set_key(0xF, frame == 5);
+            movie.record(chip8.keypad());
+            chip8.run_frame(9).unwrap();
+        }
+        movie.finish(&chip8);
+        movie
+    }
+
+    #[test]
+    fn playback_test() {
+        let movie = record(42);
+        assert_eq!(movie.frames[4], 1);
+        assert_eq!(movie.frames[5], 1 | 1 << 0xF);
+        assert_eq!(movie.key_script().events().len(), 4);
+
+        let play = |mut chip8: Chip8, seed: u64| {
+            chip8.load_rom_bytes(&ROM).unwrap();
+            chip8.set_seed(seed);
+            movie.check_start(&chip8)?;
+            let limit = RunLimit::Frames(movie.frames.len() as u64);
+            headless::run(&mut chip8, limit, 9, &movie.key_script());
+            movie.verify(&chip8)
+        };
+        assert_eq!(play(Chip8::new(), movie.seed), Ok(()));
+        assert!(play(Chip8::new(), 7).is_err());
+        assert_eq!(
+            play(Chip8::with_quirks(Quirks::vip()), movie.seed),
+            Err(format!(
+                "recorded with quirks default, not {}",
+                Quirks::vip().spec()
+            ))
+        );
+
+        let mut maze = Chip8::new();
+        maze.load_rom("maze.ch8").unwrap();
+        assert_eq!(
+            play(maze, movie.seed),
+            Err(
+                "recorded on ROM unknown, not 8b70080adbac44513ec60005734a816372b845ec".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn encode_test() {
+        let movie = record(1);
+        let bytes = movie.encode();
+        assert_eq!(bytes.len(), 49 + 2 * 12);
+        assert_eq!(Movie::decode(&bytes), Ok(movie));
+        assert_eq!(
+            Movie::decode(&bytes[..30]),
+            Err("movie is truncated".to_string())
+        );
+        assert_eq!(Movie::decode(b"C8ST\x01"), Err("not a movie".to_string()));
+    }
+}
@@ -0,0 +1,1004 @@
+//! Compiler for Octo (`.8o`) source, the assembly language most CHIP-8
+//! homebrew is written in. Covers labels, `:const`, `:alias`, `:macro`,
+//! `:calc`, `:byte`, `:org`, structured `loop`/`while`/`again` and
+//! `if ... then` / `if ... begin ... else ... end`, and the SUPER-CHIP and
+//! XO-CHIP statements. Programs start with an implicit `jump main`.
+
+use crate::chip8::START_ADDRESS;
+use std::collections::{HashMap, VecDeque};
+
+/// Nested macro expansions beyond this are assumed to be runaway recursion.
+const MAX_EXPANSIONS: usize = 10_000;
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FixupKind {
+    /// Low 12 bits of the word at the address.
+    Addr12,
+    /// The whole word at the address.
+    Addr16,
+}
+
+#[derive(Debug)]
+struct Fixup {
+    addr: usize,
+    label: String,
+    line: usize,
+    kind: FixupKind,
+}
+
+#[derive(Debug)]
+enum Flow {
+    Loop { start: usize, exits: Vec<usize> },
+    If { jump: usize },
+    Else { jump: usize },
+}
+
+/// A condition compiled to the instructions that set it up and the skips
+/// that jump over the next instruction when it is false or true.
+struct Condition {
+    prelude: Vec<u16>,
+    skip_if_false: u16,
+    skip_if_true: u16,
+}
+
+#[derive(Debug, Default)]
+struct Compiler {
+    tokens: VecDeque<Token>,
+    line: usize,
+    rom: Vec<u8>,
+    pos: usize,
+    labels: HashMap<String, usize>,
+    consts: HashMap<String, i64>,
+    aliases: HashMap<String, u16>,
+    macros: HashMap<String, (Vec<String>, Vec<Token>)>,
+    fixups: Vec<Fixup>,
+    flow: Vec<(Flow, usize)>,
+    expansions: usize,
+}
+
+/// Compiles Octo source into a ROM image to be loaded at 0x200.
+pub fn compile(src: &str) -> Result<Vec<u8>, String> {
+    let tokens = src
+        .lines()
+        .enumerate()
+        .flat_map(|(i, line)| {
+            let code = line.split('#').next().unwrap_or("");
+            code.split_whitespace().map(move |text| Token {
+                text: text.to_string(),
+                line: i + 1,
+            })
+        })
+        .collect();
+    let mut compiler = Compiler {
+        tokens,
+        pos: START_ADDRESS,
+        ..Default::default()
+    };
+    compiler.fixup("main", FixupKind::Addr12);
+    compiler.emit(0x1000);
+    while let Some(token) = compiler.next() {
+        compiler.statement(token)?;
+    }
+    if let Some((flow, line)) = compiler.flow.last() {
+        let what = match flow {
+            Flow::Loop { .. } => "loop",
+            Flow::If { .. } | Flow::Else { .. } => "if",
+        };
+        return Err(format!("line {line}: `{what}` is never closed"));
+    }
+    compiler.resolve()?;
+    Ok(compiler.rom)
+}
+
+fn number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+impl Compiler {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.pop_front()?;
+        self.line = token.line;
+        Some(token)
+    }
+
+    fn err(&self, msg: impl std::fmt::Display) -> String {
+        format!("line {}: {msg}", self.line)
+    }
+
+    fn expect(&mut self) -> Result<String, String> {
+        self.next()
+            .map(|token| token.text)
+            .ok_or_else(|| self.err("unexpected end of file"))
+    }
+
+    fn expect_token(&mut self, text: &str) -> Result<(), String> {
+        let token = self.expect()?;
+        if token != text {
+            return Err(self.err(format!("expected `{text}`, got `{token}`")));
+        }
+        Ok(())
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().is_some_and(|token| token.text == text)
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        let offset = self.pos - START_ADDRESS;
+        if self.rom.len() <= offset {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.pos += 1;
+    }
+
+    fn emit(&mut self, word: u16) {
+        let [hi, lo] = word.to_be_bytes();
+        self.write_byte(hi);
+        self.write_byte(lo);
+    }
+
+    /// Records that the word about to be emitted refers to `label`.
+    fn fixup(&mut self, label: &str, kind: FixupKind) {
+        self.fixups.push(Fixup {
+            addr: self.pos,
+            label: label.to_string(),
+            line: self.line,
+            kind,
+        });
+    }
+
+    /// Checks that a jump to `target` fits in an NNN operand.
+    fn jump_target(&self, target: usize) -> Result<u16, String> {
+        if target > 0xFFF {
+            return Err(self.err(format!("jump target 0x{target:X} is out of 12-bit range")));
+        }
+        Ok(target as u16)
+    }
+
+    fn patch(&mut self, addr: usize, target: usize) -> Result<(), String> {
+        let target = self.jump_target(target)?;
+        let offset = addr - START_ADDRESS;
+        self.rom[offset] = (self.rom[offset] & 0xF0) | (target >> 8) as u8 & 0x0F;
+        self.rom[offset + 1] = target as u8;
+        Ok(())
+    }
+
+    fn resolve(&mut self) -> Result<(), String> {
+        for fixup in std::mem::take(&mut self.fixups) {
+            let Some(&target) = self.labels.get(&fixup.label) else {
+                return Err(match fixup.label.as_str() {
+                    "main" => "no `main` label".to_string(),
+                    label => format!("line {}: undefined label `{label}`", fixup.line),
+                });
+            };
+            match fixup.kind {
+                FixupKind::Addr12 if target > 0xFFF => {
+                    return Err(format!(
+                        "line {}: `{}` is at 0x{target:X}, out of 12-bit range",
+                        fixup.line, fixup.label
+                    ));
+                }
+                FixupKind::Addr12 => self.patch(fixup.addr, target)?,
+                FixupKind::Addr16 => {
+                    let offset = fixup.addr - START_ADDRESS;
+                    self.rom[offset..offset + 2].copy_from_slice(&(target as u16).to_be_bytes());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn register_of(&self, text: &str) -> Option<u16> {
+        if let Some(&reg) = self.aliases.get(text) {
+            return Some(reg);
+        }
+        let digit = text.strip_prefix(['v', 'V'])?;
+        (digit.len() == 1)
+            .then(|| u16::from_str_radix(digit, 16).ok())
+            .flatten()
+    }
+
+    fn register(&mut self) -> Result<u16, String> {
+        let token = self.expect()?;
+        self.register_of(&token)
+            .ok_or_else(|| self.err(format!("expected a register, got `{token}`")))
+    }
+
+    fn is_identifier(text: &str) -> bool {
+        text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+    }
+
+    /// A value known now: a number, constant or already defined label.
+    fn constant(&self, text: &str) -> Result<i64, String> {
+        number(text)
+            .or_else(|| self.consts.get(text).copied())
+            .or_else(|| self.labels.get(text).map(|&addr| addr as i64))
+            .ok_or_else(|| self.err(format!("undefined name `{text}`")))
+    }
+
+    fn byte(&mut self) -> Result<u16, String> {
+        let token = self.expect()?;
+        self.byte_of(&token)
+    }
+
+    fn byte_of(&self, token: &str) -> Result<u16, String> {
+        self.fit_byte(token, self.constant(token)?)
+    }
+
+    /// `value` as a byte, accepting -128 to 255; `token` is what it came
+    /// from, for the error.
+    fn fit_byte(&self, token: &str, value: i64) -> Result<u16, String> {
+        if !(-128..=255).contains(&value) {
+            return Err(self.err(format!("`{token}` = {value} does not fit in a byte")));
+        }
+        Ok(value as u16 & 0xFF)
+    }
+
+    fn nibble(&mut self) -> Result<u16, String> {
+        let token = self.expect()?;
+        let value = self.constant(&token)?;
+        if !(0..=15).contains(&value) {
+            return Err(self.err(format!("`{token}` = {value} does not fit in a nibble")));
+        }
+        Ok(value as u16)
+    }
+
+    /// The value of an address operand for the word about to be emitted.
+    /// Labels that are not defined yet read as 0 and are patched at the end.
+    fn address(&mut self, token: &str, kind: FixupKind) -> Result<i64, String> {
+        match self.constant(token) {
+            Ok(value) => Ok(value),
+            Err(_) if Self::is_identifier(token) => {
+                self.fixup(token, kind);
+                Ok(0)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Emits `opcode` with a 12-bit address operand.
+    fn emit_addr(&mut self, opcode: u16, token: &str) -> Result<(), String> {
+        let target = self.address(token, FixupKind::Addr12)?;
+        if !(0..=0xFFF).contains(&target) {
+            return Err(self.err(format!("`{token}` = 0x{target:X} is not a 12-bit address")));
+        }
+        self.emit(opcode | target as u16);
+        Ok(())
+    }
+
+    /// Parses the body of a `{ ... }` block into its tokens.
+    fn block(&mut self) -> Result<Vec<Token>, String> {
+        self.expect_token("{")?;
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.next().ok_or_else(|| self.err("unterminated `{`"))?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(body),
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+    }
+
+    fn define_label(&mut self, name: &str, addr: usize) -> Result<(), String> {
+        if self.labels.insert(name.to_string(), addr).is_some() {
+            return Err(self.err(format!("label `{name}` is already defined")));
+        }
+        // The implicit `jump main` comes before any line, so it is
+        // reported where `main` is defined
+        if name == "main" {
+            self.fixups[0].line = self.line;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), String> {
+        let text = token.text.as_str();
+        if let Some(reg) = self.register_of(text) {
+            return self.register_statement(reg);
+        }
+        match text {
+            ":" => {
+                let name = self.expect()?;
+                self.define_label(&name, self.pos)?;
+            }
+            ":next" => {
+                // Labels the operand byte of the next instruction, for
+                // self-modifying code
+                let name = self.expect()?;
+                self.define_label(&name, self.pos + 1)?;
+            }
+            ":const" => {
+                let name = self.expect()?;
+                let value = self.expect()?;
+                let value = self.constant(&value)?;
+                self.consts.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.expect()?;
+                let reg = self.register()?;
+                self.aliases.insert(name, reg);
+            }
+            ":calc" => {
+                let name = self.expect()?;
+                let body = self.block()?;
+                let value = self.calc(&body)?;
+                self.consts.insert(name, value);
+            }
+            ":byte" => {
+                let (token, value) = if self.peek_is("{") {
+                    let body = self.block()?;
+                    (":byte { ... }".to_string(), self.calc(&body)?)
+                } else {
+                    let token = self.expect()?;
+                    let value = self.constant(&token)?;
+                    (token, value)
+                };
+                let byte = self.fit_byte(&token, value)?;
+                self.write_byte(byte as u8);
+            }
+            ":org" => {
+                let token = self.expect()?;
+                let addr = self.constant(&token)?;
+                if addr < START_ADDRESS as i64 || addr > 0xFFFF {
+                    return Err(self.err(format!("cannot place code at 0x{addr:X}")));
+                }
+                self.pos = addr as usize;
+            }
+            ":macro" => {
+                let name = self.expect()?;
+                let mut params = Vec::new();
+                while !self.peek_is("{") {
+                    params.push(self.expect()?);
+                }
+                let body = self.block()?;
+                self.macros.insert(name, (params, body));
+            }
+            ":breakpoint" => {
+                self.expect()?;
+            }
+            ":monitor" => {
+                self.expect()?;
+                self.expect()?;
+            }
+            ";" | "return" => self.emit(0x00EE),
+            "clear" => self.emit(0x00E0),
+            "exit" => self.emit(0x00FD),
+            "lores" => self.emit(0x00FE),
+            "hires" => self.emit(0x00FF),
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-left" => self.emit(0x00FC),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(0x00C0 | n);
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(0x00D0 | n);
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(0xF001 | n << 8);
+            }
+            "audio" => self.emit(0xF002),
+            "jump" => {
+                let target = self.expect()?;
+                self.emit_addr(0x1000, &target)?;
+            }
+            "jump0" => {
+                let target = self.expect()?;
+                self.emit_addr(0xB000, &target)?;
+            }
+            "native" => {
+                let target = self.expect()?;
+                self.emit_addr(0x0000, &target)?;
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(0xF033 | x << 8);
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.peek_is("-") {
+                    self.next();
+                    let y = self.register()?;
+                    let op = if text == "save" { 0x5002 } else { 0x5003 };
+                    self.emit(op | x << 8 | y << 4);
+                } else {
+                    let op = if text == "save" { 0xF055 } else { 0xF065 };
+                    self.emit(op | x << 8);
+                }
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(0xF075 | x << 8);
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(0xF085 | x << 8);
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(0xD000 | x << 8 | y << 4 | n);
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect_token(":=")?;
+                let x = self.register()?;
+                let op = match text {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.emit(op | x << 8);
+            }
+            "i" => self.index_statement()?,
+            "loop" => self.flow.push((
+                Flow::Loop {
+                    start: self.pos,
+                    exits: Vec::new(),
+                },
+                self.line,
+            )),
+            "while" => {
+                let condition = self.condition()?;
+                let Some((Flow::Loop { exits, .. }, _)) = self
+                    .flow
+                    .iter_mut()
+                    .rev()
+                    .find(|(flow, _)| matches!(flow, Flow::Loop { .. }))
+                else {
+                    return Err(self.err("`while` outside of a loop"));
+                };
+                exits.push(self.pos + 2 * (condition.prelude.len() + 1));
+                for word in condition.prelude {
+                    self.emit(word);
+                }
+                self.emit(condition.skip_if_true);
+                self.emit(0x1000);
+            }
+            "again" => match self.flow.pop() {
+                Some((Flow::Loop { start, exits }, _)) => {
+                    let start = self.jump_target(start)?;
+                    self.emit(0x1000 | start);
+                    for exit in exits {
+                        self.patch(exit, self.pos)?;
+                    }
+                }
+                _ => return Err(self.err("`again` without `loop`")),
+            },
+            "if" => {
+                let condition = self.condition()?;
+                for &word in &condition.prelude {
+                    self.emit(word);
+                }
+                match self.expect()?.as_str() {
+                    "then" => self.emit(condition.skip_if_false),
+                    "begin" => {
+                        self.emit(condition.skip_if_true);
+                        self.flow.push((Flow::If { jump: self.pos }, self.line));
+                        self.emit(0x1000);
+                    }
+                    other => {
+                        return Err(self.err(format!("expected `then` or `begin`, got `{other}`")));
+                    }
+                }
+            }
+            "else" => match self.flow.pop() {
+                Some((Flow::If { jump }, line)) => {
+                    let end = self.pos;
+                    self.emit(0x1000);
+                    self.patch(jump, self.pos)?;
+                    self.flow.push((Flow::Else { jump: end }, line));
+                }
+                _ => return Err(self.err("`else` without `if ... begin`")),
+            },
+            "end" => match self.flow.pop() {
+                Some((Flow::If { jump } | Flow::Else { jump }, _)) => self.patch(jump, self.pos)?,
+                _ => return Err(self.err("`end` without `if ... begin`")),
+            },
+            _ => {
+                if let Some((params, body)) = self.macros.get(text).cloned() {
+                    return self.expand(&params, body);
+                }
+                if let Some(value) = number(text).or_else(|| self.consts.get(text).copied()) {
+                    let byte = self.fit_byte(text, value)?;
+                    self.write_byte(byte as u8);
+                } else if Self::is_identifier(text) {
+                    // A bare label is a subroutine call
+                    self.emit_addr(0x2000, text)?;
+                } else {
+                    return Err(self.err(format!("unexpected `{text}`")));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn expand(&mut self, params: &[String], body: Vec<Token>) -> Result<(), String> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.err("too many macro expansions"));
+        }
+        let mut args = HashMap::new();
+        for param in params {
+            args.insert(param.clone(), self.expect()?);
+        }
+        let line = self.line;
+        for token in body.into_iter().rev() {
+            let text = args.get(&token.text).cloned().unwrap_or(token.text);
+            self.tokens.push_front(Token { text, line });
+        }
+        Ok(())
+    }
+
+    fn index_statement(&mut self) -> Result<(), String> {
+        match self.expect()?.as_str() {
+            "+=" => {
+                let x = self.register()?;
+                self.emit(0xF01E | x << 8);
+            }
+            ":=" if self.peek_is("hex") || self.peek_is("bighex") => {
+                let op = if self.expect()? == "hex" {
+                    0xF029
+                } else {
+                    0xF030
+                };
+                let x = self.register()?;
+                self.emit(op | x << 8);
+            }
+            ":=" if self.peek_is("long") => {
+                self.next();
+                let token = self.expect()?;
+                self.emit(0xF000);
+                let addr = self.address(&token, FixupKind::Addr16)?;
+                self.emit(addr as u16);
+            }
+            ":=" => {
+                let target = self.expect()?;
+                self.emit_addr(0xA000, &target)?;
+            }
+            other => {
+                return Err(self.err(format!("expected `:=` or `+=` after `i`, got `{other}`")));
+            }
+        }
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: u16) -> Result<(), String> {
+        let op = self.expect()?;
+        let rhs = self.expect()?;
+        let word = match (op.as_str(), rhs.as_str(), self.register_of(&rhs)) {
+            (":=", "random", _) => 0xC000 | x << 8 | self.byte()?,
+            (":=", "delay", _) => 0xF007 | x << 8,
+            (":=", "key", _) => 0xF00A | x << 8,
+            (":=", _, Some(y)) => 0x8000 | x << 8 | y << 4,
+            (":=", _, None) => 0x6000 | x << 8 | self.byte_of(&rhs)?,
+            ("+=", _, Some(y)) => 0x8004 | x << 8 | y << 4,
+            ("+=", _, None) => 0x7000 | x << 8 | self.byte_of(&rhs)?,
+            ("-=", _, None) => 0x7000 | x << 8 | (self.byte_of(&rhs)?.wrapping_neg() & 0xFF),
+            (op, _, Some(y)) => {
+                let n = match op {
+                    "|=" => 0x1,
+                    "&=" => 0x2,
+                    "^=" => 0x3,
+                    "-=" => 0x5,
+                    ">>=" => 0x6,
+                    "=-" => 0x7,
+                    "<<=" => 0xE,
+                    _ => return Err(self.err(format!("unknown operator `{op}`"))),
+                };
+                0x8000 | x << 8 | y << 4 | n
+            }
+            (op, rhs, None) => {
+                return Err(self.err(format!("`{op}` needs a register, got `{rhs}`")));
+            }
+        };
+        self.emit(word);
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let x = self.register()?;
+        let op = self.expect()?;
+        if op == "key" || op == "-key" {
+            let (pressed, released) = (0xE09E | x << 8, 0xE0A1 | x << 8);
+            let (skip_if_false, skip_if_true) = if op == "key" {
+                (released, pressed)
+            } else {
+                (pressed, released)
+            };
+            return Ok(Condition {
+                prelude: Vec::new(),
+                skip_if_false,
+                skip_if_true,
+            });
+        }
+        let rhs = self.expect()?;
+        let y = self.register_of(&rhs);
+        let load_rhs = match y {
+            Some(y) => 0x8F00 | y << 4,
+            None => 0x6F00 | self.byte_of(&rhs)?,
+        };
+        let (eq, ne) = match y {
+            Some(y) => (0x5000 | x << 8 | y << 4, 0x9000 | x << 8 | y << 4),
+            None => {
+                let nn = load_rhs & 0xFF;
+                (0x3000 | x << 8 | nn, 0x4000 | x << 8 | nn)
+            }
+        };
+        // Ordered comparisons subtract into vF and test the borrow flag:
+        // vf =- vx leaves vf = (vx >= rhs), vf -= vx leaves vf = (rhs >= vx)
+        let (prelude, holds_when_flag_set) = match op.as_str() {
+            "==" => {
+                return Ok(Condition {
+                    prelude: Vec::new(),
+                    skip_if_false: ne,
+                    skip_if_true: eq,
+                });
+            }
+            "!=" => {
+                return Ok(Condition {
+                    prelude: Vec::new(),
+                    skip_if_false: eq,
+                    skip_if_true: ne,
+                });
+            }
+            "<" => (vec![load_rhs, 0x8F07 | x << 4], false),
+            ">=" => (vec![load_rhs, 0x8F07 | x << 4], true),
+            ">" => (vec![load_rhs, 0x8F05 | x << 4], false),
+            "<=" => (vec![load_rhs, 0x8F05 | x << 4], true),
+            _ => return Err(self.err(format!("unknown comparison `{op}`"))),
+        };
+        // SE vf, 0 skips when the flag is clear; SNE vf, 0 when it is set
+        let (clear, set) = (0x3F00, 0x4F00);
+        let (skip_if_false, skip_if_true) = if holds_when_flag_set {
+            (clear, set)
+        } else {
+            (set, clear)
+        };
+        Ok(Condition {
+            prelude,
+            skip_if_false,
+            skip_if_true,
+        })
+    }
+
+    /// Evaluates a `:calc` expression. As in Octo, binary operators have no
+    /// precedence and group to the right; use parentheses to override.
+    fn calc(&self, tokens: &[Token]) -> Result<i64, String> {
+        let mut pos = 0;
+        let value = self.calc_expr(tokens, &mut pos)?;
+        if pos != tokens.len() {
+            return Err(self.err(format!("unexpected `{}` in expression", tokens[pos].text)));
+        }
+        Ok(value.floor() as i64)
+    }
+
+    fn calc_expr(&self, tokens: &[Token], pos: &mut usize) -> Result<f64, String> {
+        let lhs = self.calc_term(tokens, pos)?;
+        let Some(op) = tokens.get(*pos).filter(|t| t.text != ")") else {
+            return Ok(lhs);
+        };
+        *pos += 1;
+        let rhs = self.calc_expr(tokens, pos)?;
+        let (a, b) = (lhs as i64, rhs as i64);
+        Ok(match op.text.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" => lhs / rhs,
+            "%" => lhs % rhs,
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" | ">>" => {
+                let shifted = u32::try_from(b).ok().and_then(|b| match op.text.as_str() {
+                    "<<" => a.checked_shl(b),
+                    _ => a.checked_shr(b),
+                });
+                shifted.ok_or_else(|| self.err(format!("cannot shift by {b}")))? as f64
+            }
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            "<" => (lhs < rhs) as u8 as f64,
+            ">" => (lhs > rhs) as u8 as f64,
+            "<=" => (lhs <= rhs) as u8 as f64,
+            ">=" => (lhs >= rhs) as u8 as f64,
+            "==" => (lhs == rhs) as u8 as f64,
+            "!=" => (lhs != rhs) as u8 as f64,
+            other => return Err(self.err(format!("unknown operator `{other}`"))),
+        })
+    }
+
+    fn calc_term(&self, tokens: &[Token], pos: &mut usize) -> Result<f64, String> {
+        let token = tokens
+            .get(*pos)
+            .ok_or_else(|| self.err("unexpected end of expression"))?;
+        *pos += 1;
+        match token.text.as_str() {
+            "(" => {
+                let value = self.calc_expr(tokens, pos)?;
+                if tokens.get(*pos).is_none_or(|t| t.text != ")") {
+                    return Err(self.err("missing `)`"));
+                }
+                *pos += 1;
+                Ok(value)
+            }
+            "-" => Ok(-self.calc_term(tokens, pos)?),
+            "~" => Ok(!(self.calc_term(tokens, pos)? as i64) as f64),
+            "!" => Ok((self.calc_term(tokens, pos)? == 0.0) as u8 as f64),
+            "HERE" => Ok(self.pos as f64),
+            text => match text.parse::<f64>() {
+                Ok(value) if text.starts_with(|c: char| c.is_ascii_digit()) => Ok(value),
+                _ => self.constant(text).map(|value| value as f64),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{Chip8, Mode};
+    use crate::headless::{KeyScript, RunLimit, run};
+    use crate::quirks::Quirks;
+
+    fn words(rom: &[u8]) -> Vec<u16> {
+        rom.chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair.get(1).copied().unwrap_or(0)]))
+            .collect()
+    }
+
+    #[test]
+    fn statements_test() {
+        let rom = compile(
+            "
+            :alias x v3
+            :const SPEED 2
+            : main
+                clear
+                x := SPEED
+                x += -1
+                v1 := random 0x0F
+                v2 += x
+                v2 >>= v2
+                i := ball
+                sprite v1 x 5
+                delay := v2
+                v4 := key
+                bcd v4
+                save v3
+                load v0 - v2
+                draw
+                return
+            : draw
+                i := long ball
+                ;
+            : ball
+                0x80 0b01000000 :byte { SPEED * 3 }
+            ",
+        )
+        .expect("should compile");
+        assert_eq!(
+            words(&rom),
+            [
+                0x1202, 0x00E0, 0x6302, 0x73FF, 0xC10F, 0x8234, 0x8226, 0xA226, 0xD135, 0xF215,
+                0xF40A, 0xF433, 0xF355, 0x5023, 0x2220, 0x00EE, 0xF000, 0x0226, 0x00EE, 0x8040,
+                0x0600,
+            ]
+        );
+    }
+
+    #[test]
+    fn control_flow_test() {
+        // Counts v0 up to 10 and records in v1 which branches ran
+        let src = "
+            : main
+                loop
+                    v0 += 1
+                    while v0 != 10
+                    if v0 > 5 begin
+                        v1 += 1
+                    else
+                        v2 += 1
+                    end
+                    if v0 == 3 then v3 := 0xAA
+                again
+                :macro halt { : stop jump stop }
+                halt
+        ";
+        let rom = compile(src).expect("should compile");
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&rom).unwrap();
+        let report = run(
+            &mut chip8,
+            RunLimit::Instructions(500),
+            10,
+            &KeyScript::default(),
+        );
+        assert_eq!(report.error, None);
+        assert_eq!(chip8.registers()[0], 10);
+        assert_eq!(chip8.registers()[1], 4);
+        assert_eq!(chip8.registers()[2], 5);
+        assert_eq!(chip8.registers()[3], 0xAA);
+    }
+
+    #[test]
+    fn comparison_test() {
+        // v2 gets a bit for every comparison that holds between v0 and v1
+        for (a, b) in [(3, 7), (7, 3), (5, 5)] {
+            let src = format!(
+                ": main v0 := {a} v1 := {b}
+                 if v0 < v1 then v2 += 1
+                 if v0 > v1 then v2 += 2
+                 if v0 <= v1 then v2 += 4
+                 if v0 >= v1 then v2 += 8
+                 if v0 < {b} then v2 += 16
+                 : stop jump stop"
+            );
+            let mut chip8 = Chip8::new();
+            chip8.load_rom_bytes(&compile(&src).unwrap()).unwrap();
+            run(
+                &mut chip8,
+                RunLimit::Instructions(100),
+                10,
+                &KeyScript::default(),
+            );
+            let expected = match a.cmp(&b) {
+                std::cmp::Ordering::Less => 1 | 4 | 16,
+                std::cmp::Ordering::Greater => 2 | 8,
+                std::cmp::Ordering::Equal => 4 | 8,
+            };
+            assert_eq!(chip8.registers()[2], expected, "{a} vs {b}");
+        }
+    }
+
+    #[test]
+    fn xochip_test() {
+        let src = "
+            :calc FRAMES { 2 * ( 3 + 1 ) }
+            :org 0x300
+            : main
+                hires plane 3 scroll-down 4 scroll-up 2
+                pitch := v0 audio
+                v0 := FRAMES
+                exit
+        ";
+        let rom = compile(src).unwrap();
+        assert_eq!(words(&rom[..2]), [0x1300]);
+        assert_eq!(
+            words(&rom[0x100..]),
+            [
+                0x00FF, 0xF301, 0x00C4, 0x00D2, 0xF03A, 0xF002, 0x6008, 0x00FD
+            ]
+        );
+        let mut chip8 = Chip8::with_mode(Mode::XoChip, Quirks::xochip());
+        chip8.load_rom_bytes(&rom).unwrap();
+        run(
+            &mut chip8,
+            RunLimit::Instructions(20),
+            10,
+            &KeyScript::default(),
+        );
+        assert!(chip8.exited());
+        assert_eq!(chip8.planes(), 3);
+    }
+
+    #[test]
+    fn errors_test() {
+        assert_eq!(compile("clear").unwrap_err(), "no `main` label");
+        assert_eq!(
+            compile(": main\n jump nowhere").unwrap_err(),
+            "line 2: undefined label `nowhere`"
+        );
+        assert_eq!(
+            compile(": main\n\n v0 := 300").unwrap_err(),
+            "line 3: `300` = 300 does not fit in a byte"
+        );
+        assert_eq!(
+            compile(": main loop\n v0 += 1").unwrap_err(),
+            "line 1: `loop` is never closed"
+        );
+        assert_eq!(
+            compile(": main\nagain").unwrap_err(),
+            "line 2: `again` without `loop`"
+        );
+        assert_eq!(
+            compile(": main\nv0 := vz").unwrap_err(),
+            "line 2: undefined name `vz`"
+        );
+        assert_eq!(
+            compile(": main : main").unwrap_err(),
+            "line 1: label `main` is already defined"
+        );
+        assert_eq!(
+            compile(
+                ": main
+:byte 256"
+            )
+            .unwrap_err(),
+            "line 2: `256` = 256 does not fit in a byte"
+        );
+        assert_eq!(
+            compile(
+                ": main
+:byte { 100 * 3 }"
+            )
+            .unwrap_err(),
+            "line 2: `:byte { ... }` = 300 does not fit in a byte"
+        );
+        assert_eq!(
+            compile(
+                ": main
+-129"
+            )
+            .unwrap_err(),
+            "line 2: `-129` = -129 does not fit in a byte"
+        );
+        assert_eq!(
+            compile(
+                ": main
+:calc x { 1 << 64 }"
+            )
+            .unwrap_err(),
+            "line 2: cannot shift by 64"
+        );
+        assert_eq!(
+            compile(
+                ": main
+:calc x { 1 >> -1 }"
+            )
+            .unwrap_err(),
+            "line 2: cannot shift by -1"
+        );
+        assert_eq!(
+            compile(
+                ":org 0x1000
+: main"
+            )
+            .unwrap_err(),
+            "line 2: `main` is at 0x1000, out of 12-bit range"
+        );
+        assert_eq!(
+            compile(
+                ": main jump far
+:org 0x1000 : far loop
+again"
+            )
+            .unwrap_err(),
+            "line 3: jump target 0x1000 is out of 12-bit range"
+        );
+        assert_eq!(
+            compile(
+                ": main jump far
+:org 0xFFE : far if v0 == 1 begin
+end"
+            )
+            .unwrap_err(),
+            "line 3: jump target 0x1002 is out of 12-bit range"
+        );
+    }
+}
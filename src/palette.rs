@@ -0,0 +1,147 @@
+//! Screen colours. A palette maps each pixel's plane bits to a colour:
+//! background, plane 1, plane 2 and both planes. Plain CHIP-8 and
+//! SUPER-CHIP only ever light plane 1; the other two are for XO-CHIP.
+
+/// A palette of 0xRRGGBB colours indexed by the plane bits of a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette(pub [u32; 4]);
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::CLASSIC
+    }
+}
+
+impl Palette {
+    /// White on black, with greys for the XO-CHIP planes.
+    pub const CLASSIC: Palette = Palette([0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555]);
+    /// A green phosphor CRT.
+    pub const GREEN: Palette = Palette([0x001100, 0x33FF66, 0x119944, 0xAAFFBB]);
+    /// An amber monochrome monitor.
+    pub const AMBER: Palette = Palette([0x1A0F00, 0xFFB000, 0x996600, 0xFFDD88]);
+    /// The original Game Boy's four greens, dark on light.
+    pub const GAMEBOY: Palette = Palette([0x9BBC0F, 0x0F380F, 0x8BAC0F, 0x306230]);
+    /// Saturated colours far apart, for low vision.
+    pub const HIGH_CONTRAST: Palette = Palette([0x000000, 0xFFFF00, 0x00FFFF, 0xFFFFFF]);
+
+    /// The named presets, in the order the frontend cycles through them.
+    pub const PRESETS: [(&'static str, Palette); 5] = [
+        ("classic", Palette::CLASSIC),
+        ("green", Palette::GREEN),
+        ("amber", Palette::AMBER),
+        ("gameboy", Palette::GAMEBOY),
+        ("high-contrast", Palette::HIGH_CONTRAST),
+    ];
+
+    /// Looks up a preset by name, e.g. `"amber"`.
+    pub fn from_name(name: &str) -> Option<Palette> {
+        let name = name.to_ascii_lowercase();
+        let name = match name.as_str() {
+            "default" | "mono" => "classic",
+            "phosphor" | "green-phosphor" => "green",
+            "game-boy" | "dmg" => "gameboy",
+            "contrast" => "high-contrast",
+            name => name,
+        };
+        Palette::PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|&(_, palette)| palette)
+    }
+
+    /// Parses a preset name, or two to four `RRGGBB` colours (`#` optional)
+    /// for the background, plane 1, plane 2 and both planes. Colours left
+    /// out are taken from the classic palette.
+    pub fn parse(spec: &str) -> Result<Palette, String> {
+        if let Some(palette) = Palette::from_name(spec.trim()) {
+            return Ok(palette);
+        }
+        let colours = spec
+            .split(',')
+            .map(|colour| {
+                let hex = colour.trim().trim_start_matches('#');
+                u32::from_str_radix(hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 6)
+                    .ok_or(format!("invalid colour: {colour}"))
+            })
+            .collect::<Result<Vec<u32>, String>>()?;
+        if !(2..=4).contains(&colours.len()) {
+            return Err(format!("expected 2 to 4 colours, got {}", colours.len()));
+        }
+        let mut palette = Palette::CLASSIC;
+        palette.0[..colours.len()].copy_from_slice(&colours);
+        Ok(palette)
+    }
+
+    /// The colour of a pixel with plane bits `pixel`.
+    pub fn colour(&self, pixel: u32) -> u32 {
+        self.0[pixel as usize & 0x3]
+    }
+
+    /// The name of this palette if it is a preset.
+    pub fn name(&self) -> Option<&'static str> {
+        Palette::PRESETS
+            .iter()
+            .find(|(_, preset)| preset == self)
+            .map(|&(name, _)| name)
+    }
+
+    /// The preset after this one, wrapping around; the first preset if
+    /// this is a custom palette.
+    pub fn next_preset(&self) -> (&'static str, Palette) {
+        let next = match Palette::PRESETS.iter().position(|(_, p)| p == self) {
+            Some(i) => (i + 1) % Palette::PRESETS.len(),
+            None => 0,
+        };
+        Palette::PRESETS[next]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(Palette::parse("Amber"), Ok(Palette::AMBER));
+        assert_eq!(Palette::parse("phosphor"), Ok(Palette::GREEN));
+        assert_eq!(
+            Palette::parse("#102030, 405060"),
+            Ok(Palette([0x102030, 0x405060, 0xAAAAAA, 0x555555]))
+        );
+        assert_eq!(
+            Palette::parse("000000,111111,222222,333333")
+                .unwrap()
+                .colour(3),
+            0x333333
+        );
+        assert!(Palette::parse("000000").is_err());
+        assert!(Palette::parse("000000,12345").is_err());
+        assert!(Palette::parse("sepia").is_err());
+    }
+
+    #[test]
+    fn cycle_test() {
+        let mut palette = Palette([1, 2, 3, 4]);
+        assert_eq!(palette.name(), None);
+        let mut names = Vec::new();
+        for _ in 0..Palette::PRESETS.len() + 1 {
+            let (name, next) = palette.next_preset();
+            assert_eq!(next.name(), Some(name));
+            names.push(name);
+            palette = next;
+        }
+        assert_eq!(
+            names,
+            [
+                "classic",
+                "green",
+                "amber",
+                "gameboy",
+                "high-contrast",
+                "classic"
+            ]
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Minimal PNG encoder for framebuffer dumps. Pixel data goes out in
+//! uncompressed deflate blocks, which keeps this dependency-free at the cost
+//! of file size (a 64x32 screen is still only a few KiB).
+
+use crate::palette::Palette;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// Encodes a framebuffer as an 8-bit greyscale PNG, lit pixels white, each
+/// pixel blown up to `scale` x `scale`.
+pub fn encode_framebuffer(video: &[u32], width: usize, height: usize, scale: usize) -> Vec<u8> {
+    let scale = scale.max(1);
+    let (out_w, out_h) = (width * scale, height * scale);
+
+    // Each scanline is prefixed with filter type 0 (None)
+    let mut raw = Vec::with_capacity((out_w + 1) * out_h);
+    for y in 0..out_h {
+        raw.push(0);
+        let row = &video[(y / scale) * width..(y / scale + 1) * width];
+        for x in 0..out_w {
+            raw.push(if row[x / scale] != 0 { 0xFF } else { 0x00 });
+        }
+    }
+    encode_image(out_w, out_h, COLOUR_GREYSCALE, &raw)
+}
+
+/// Encodes a framebuffer as an 8-bit RGB PNG in `palette`'s colours, so
+/// XO-CHIP planes come out distinct.
+pub fn encode_framebuffer_rgb(
+    video: &[u32],
+    width: usize,
+    height: usize,
+    scale: usize,
+    palette: &Palette,
+) -> Vec<u8> {
+    let scale = scale.max(1);
+    let (out_w, out_h) = (width * scale, height * scale);
+
+    let mut raw = Vec::with_capacity((out_w * 3 + 1) * out_h);
+    for y in 0..out_h {
+        raw.push(0);
+        let row = &video[(y / scale) * width..(y / scale + 1) * width];
+        for x in 0..out_w {
+            let [_, r, g, b] = palette.colour(row[x / scale]).to_be_bytes();
+            raw.extend_from_slice(&[r, g, b]);
+        }
+    }
+    encode_image(out_w, out_h, COLOUR_RGB, &raw)
+}
+
+const COLOUR_GREYSCALE: u8 = 0;
+const COLOUR_RGB: u8 = 2;
+
+/// Wraps filtered scanlines `raw` up as a PNG file.
+fn encode_image(out_w: usize, out_h: usize, colour_type: u8, raw: &[u8]) -> Vec<u8> {
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(out_w as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(out_h as u32).to_be_bytes());
+    // bit depth 8, deflate, adaptive filtering, no interlace
+    ihdr.extend_from_slice(&[8, colour_type, 0, 0, 0]);
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut chunks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        let len = chunk.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_test() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn encode_framebuffer_test() {
+        let video = [0xFFFF_FFFF, 0, 0, 0xFFFF_FFFF];
+        let png = encode_framebuffer(&video, 2, 2, 2);
+        assert_eq!(png[..8], SIGNATURE);
+        // IHDR width/height are the scaled dimensions
+        assert_eq!(png[16..24], [0, 0, 0, 4, 0, 0, 0, 4]);
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+    }
+
+    #[test]
+    fn encode_framebuffer_rgb_test() {
+        let video = [0, 1, 2, 3];
+        let png = encode_framebuffer_rgb(&video, 4, 1, 1, &Palette::GAMEBOY);
+        // 8-bit RGB
+        assert_eq!(png[24..26], [8, 2]);
+        // The stored block's scanline: filter byte, then each pixel's colour
+        let raw = &png[33 + 8 + 2 + 5..][..13];
+        assert_eq!(
+            raw,
+            [
+                0, 0x9B, 0xBC, 0x0F, 0x0F, 0x38, 0x0F, 0x8B, 0xAC, 0x0F, 0x30, 0x62, 0x30
+            ]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+/// Switches for the opcodes whose behaviour differs between CHIP-8
+/// implementations. `Quirks::default()` matches what this emulator has
+/// always done; the named presets match the original platforms.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VY into VX instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// FX55/FX65 leave I pointing past the last register stored/loaded.
+    pub load_store_increments_i: bool,
+    /// BNNN jumps to XNN + VX instead of NNN + V0.
+    pub jump_uses_vx: bool,
+    /// DXYN clips sprites at the screen edge instead of wrapping them.
+    pub clip_sprites: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub vf_reset: bool,
+    /// DXYN waits for the next vertical blank, limiting draws to one per frame.
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    pub const PRESETS: [&'static str; 4] = ["vip", "chip48", "schip", "xochip"];
+
+    /// The original COSMAC VIP interpreter.
+    pub fn vip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            clip_sprites: true,
+            vf_reset: true,
+            display_wait: true,
+        }
+    }
+
+    /// CHIP-48 on the HP-48 calculators.
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: true,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            vf_reset: false,
+            display_wait: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1.
+    pub fn schip() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            vf_reset: false,
+            display_wait: false,
+        }
+    }
+
+    /// XO-CHIP as implemented by Octo.
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            vf_reset: false,
+            display_wait: false,
+        }
+    }
+
+    /// Looks up a preset by name, e.g. `"vip"` or `"schip"`.
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name.to_ascii_lowercase().as_str() {
+            "default" => Some(Quirks::default()),
+            "vip" | "chip8" | "chip-8" => Some(Quirks::vip()),
+            "chip48" | "chip-48" => Some(Quirks::chip48()),
+            "schip" | "schip11" | "superchip" => Some(Quirks::schip()),
+            "xochip" | "xo-chip" => Some(Quirks::xochip()),
+            _ => None,
+        }
+    }
+
+    /// The switch a flag name in a quirks spec stands for.
+    fn flag(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "shift" => Some(&mut self.shift_uses_vy),
+            "memory" => Some(&mut self.load_store_increments_i),
+            "jump" => Some(&mut self.jump_uses_vx),
+            "clip" => Some(&mut self.clip_sprites),
+            "vf-reset" => Some(&mut self.vf_reset),
+            "vblank" => Some(&mut self.display_wait),
+            _ => None,
+        }
+    }
+
+    /// Parses a preset name optionally followed by switches turned on or
+    /// off, e.g. `"schip,+vblank,-clip"`. The switches are `shift`,
+    /// `memory`, `jump`, `clip`, `vf-reset` and `vblank`.
+    pub fn parse(spec: &str) -> Result<Quirks, String> {
+        let mut parts = spec.split(',').map(str::trim);
+        let preset = parts.next().unwrap_or_default();
+        let mut quirks =
+            Quirks::from_name(preset).ok_or(format!("unknown quirks preset: {preset}"))?;
+        for part in parts {
+            let (on, name) = match part.split_at_checked(1) {
+                Some(("+", name)) => (true, name),
+                Some(("-", name)) => (false, name),
+                _ => return Err(format!("expected +switch or -switch, got `{part}`")),
+            };
+            *quirks
+                .flag(name)
+                .ok_or(format!("unknown quirk switch: {name}"))? = on;
+        }
+        Ok(quirks)
+    }
+
+    /// These quirks as a spec [`Quirks::parse`] accepts, relative to the
+    /// default preset.
+    pub fn spec(&self) -> String {
+        let mut spec = String::from("default");
+        let (mut quirks, mut base) = (*self, Quirks::default());
+        for name in ["shift", "memory", "jump", "clip", "vf-reset", "vblank"] {
+            let on = *quirks.flag(name).expect("known switch");
+            if *base.flag(name).expect("known switch") != on {
+                spec.push_str(if on { ",+" } else { ",-" });
+                spec.push_str(name);
+            }
+        }
+        spec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_name_test() {
+        assert_eq!(Quirks::from_name("VIP"), Some(Quirks::vip()));
+        assert_eq!(Quirks::from_name("xo-chip"), Some(Quirks::xochip()));
+        assert_eq!(Quirks::from_name("nope"), None);
+        for name in Quirks::PRESETS {
+            assert!(Quirks::from_name(name).is_some());
+        }
+    }
+
+    #[test]
+    fn parse_test() {
+        let quirks = Quirks::parse("schip, +vblank,-clip").unwrap();
+        assert!(quirks.display_wait && !quirks.clip_sprites && quirks.jump_uses_vx);
+        assert_eq!(Quirks::parse(&quirks.spec()), Ok(quirks));
+        assert_eq!(
+            Quirks::vip().spec(),
+            "default,+shift,+memory,+clip,+vf-reset,+vblank"
+        );
+        assert_eq!(Quirks::default().spec(), "default");
+        assert!(Quirks::parse("vip,clip").is_err());
+        assert!(Quirks::parse("vip,+wobble").is_err());
+        assert!(Quirks::parse("").is_err());
+    }
+}
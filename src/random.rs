@@ -0,0 +1,122 @@
+//! Random number sources for CXNN. The machine owns one as a
+//! `Box<dyn Random>`, so a frontend can swap the generator or reseed it to
+//! make a run reproducible.
+
+use std::fmt;
+
+pub trait Random: fmt::Debug + Send {
+    /// The next byte for CXNN, before it is masked with NN.
+    fn next_byte(&mut self) -> u8;
+
+    /// Restarts the sequence from `seed`.
+    fn reseed(&mut self, seed: u64);
+
+    /// The name [`from_name`] knows this generator by.
+    fn kind(&self) -> &'static str;
+
+    /// Where the generator is in its sequence, for save states.
+    fn state(&self) -> u64;
+
+    /// Carries on the sequence from a [`Random::state`].
+    fn restore(&mut self, state: u64);
+
+    fn clone_box(&self) -> Box<dyn Random>;
+}
+
+impl Clone for Box<dyn Random> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Picks a generator by name. There is only `splitmix` (the default) so far.
+pub fn from_name(name: &str, seed: u64) -> Option<Box<dyn Random>> {
+    match name.to_ascii_lowercase().as_str() {
+        "default" | "splitmix" => Some(Box::new(SplitMix64::new(seed))),
+        _ => None,
+    }
+}
+
+/// SplitMix64. Implemented here rather than taken from `rand` so that a seed
+/// gives the same sequence whatever version of `rand` is in use, which
+/// recorded movies rely on.
+#[derive(Debug, Clone)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64 { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl Random for SplitMix64 {
+    fn next_byte(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.state = seed;
+    }
+
+    fn kind(&self) -> &'static str {
+        "splitmix"
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn restore(&mut self, state: u64) {
+        self.state = state;
+    }
+
+    fn clone_box(&self) -> Box<dyn Random> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splitmix_test() {
+        let mut a = SplitMix64::new(7);
+        let mut b = a.clone_box();
+        let sequence: Vec<u8> = (0..1000).map(|_| a.next_byte()).collect();
+        assert!((0..1000).all(|i| b.next_byte() == sequence[i]));
+
+        // Every byte value turns up, 255 included
+        let mut seen = [false; 256];
+        let mut rng = SplitMix64::new(0);
+        (0..10_000).for_each(|_| seen[rng.next_byte() as usize] = true);
+        assert!(seen.iter().all(|&s| s));
+
+        a.reseed(7);
+        assert_eq!(a.next_byte(), sequence[0]);
+    }
+
+    #[test]
+    fn restore_test() {
+        let mut rng = from_name("splitmix", 3).unwrap();
+        assert_eq!(rng.kind(), "splitmix");
+        rng.next_byte();
+        let state = rng.state();
+        let next: Vec<u8> = (0..10).map(|_| rng.next_byte()).collect();
+
+        let mut restored = from_name(rng.kind(), 0).unwrap();
+        restored.restore(state);
+        assert!((0..10).all(|i| restored.next_byte() == next[i]));
+        assert!(from_name("vip", 0).is_none());
+    }
+}
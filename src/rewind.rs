@@ -0,0 +1,212 @@
+//! Rewind: a ring buffer of per-frame snapshots that can be stepped back
+//! through one frame at a time.
+//!
+//! Only the newest snapshot is kept whole, as an encoded [`SaveState`].
+//! Every older frame is a [`Delta`] that turns the snapshot after it back
+//! into itself, so consecutive frames that differ in a few bytes cost a few
+//! bytes. The oldest deltas are dropped once either the frame budget or the
+//! memory cap is exceeded.
+
+use crate::chip8::{Chip8, TIMER_HZ};
+use crate::state::SaveState;
+use std::collections::VecDeque;
+
+/// Differing spans closer together than this are stored as one run.
+const MERGE_GAP: usize = 8;
+/// Bookkeeping cost of one run, counted towards the memory cap.
+const RUN_OVERHEAD: usize = 16;
+
+/// The bytes that turn one encoded snapshot into another.
+#[derive(Debug, Clone)]
+struct Delta {
+    len: usize,
+    runs: Vec<(usize, Vec<u8>)>,
+}
+
+impl Delta {
+    /// The delta that turns `from` into `to`.
+    fn between(from: &[u8], to: &[u8]) -> Delta {
+        let differs = |i: usize| from.get(i) != Some(&to[i]);
+        let mut runs: Vec<(usize, Vec<u8>)> = Vec::new();
+        let mut i = 0;
+        while i < to.len() {
+            if !differs(i) {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            let mut end = i + 1;
+            let mut j = end;
+            while j < to.len() && j < end + MERGE_GAP {
+                if differs(j) {
+                    end = j + 1;
+                }
+                j += 1;
+            }
+            runs.push((start, to[start..end].to_vec()));
+            i = end;
+        }
+        Delta {
+            len: to.len(),
+            runs,
+        }
+    }
+
+    fn apply(&self, bytes: &mut Vec<u8>) {
+        bytes.resize(self.len, 0);
+        for (start, run) in &self.runs {
+            bytes[*start..*start + run.len()].copy_from_slice(run);
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.runs
+            .iter()
+            .map(|(_, run)| run.len() + RUN_OVERHEAD)
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rewind {
+    /// The most recent snapshot, encoded.
+    head: Option<Vec<u8>>,
+    /// Oldest first; the last delta turns `head` into the frame before it.
+    deltas: VecDeque<Delta>,
+    max_frames: usize,
+    max_bytes: usize,
+    bytes: usize,
+}
+
+impl Rewind {
+    /// Keeps up to `seconds` of frames, using at most `max_bytes` for the
+    /// frame deltas.
+    pub fn new(seconds: u32, max_bytes: usize) -> Rewind {
+        Rewind {
+            head: None,
+            deltas: VecDeque::new(),
+            max_frames: (seconds as usize).saturating_mul(TIMER_HZ as usize),
+            max_bytes,
+            bytes: 0,
+        }
+    }
+
+    /// Records the machine as it is now. Call once per frame.
+    pub fn push(&mut self, chip8: &Chip8) {
+        let snapshot = chip8.save_state().encode();
+        if let Some(head) = self.head.take() {
+            let delta = Delta::between(&snapshot, &head);
+            self.bytes += delta.size();
+            self.deltas.push_back(delta);
+        }
+        self.head = Some(snapshot);
+        while self.deltas.len() > self.max_frames || self.bytes > self.max_bytes {
+            match self.deltas.pop_front() {
+                Some(delta) => self.bytes -= delta.size(),
+                None => break,
+            }
+        }
+    }
+
+    /// Puts `chip8` back one frame. Returns false once there is nothing
+    /// further back to go to.
+    pub fn step_back(&mut self, chip8: &mut Chip8) -> bool {
+        let (Some(head), Some(delta)) = (self.head.as_mut(), self.deltas.pop_back()) else {
+            return false;
+        };
+        self.bytes -= delta.size();
+        delta.apply(head);
+        let state = SaveState::decode(head).expect("rewind snapshots decode");
+        chip8.load_state(state);
+        true
+    }
+
+    /// Frames that can be stepped back through.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Memory used by the frame deltas.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.head = None;
+        self.deltas.clear();
+        self.bytes = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LD V0, 1; ADD V0, 1; LD I, 0x300; LD [I], V0; CLS; DRW V0, V0, 5; JP 0x202
+    const COUNTER: [u8; 14] = [
+        0x60, 0x01, 0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x00, 0xE0, 0xD0, 0x05, 0x12, 0x02,
+    ];
+
+    #[test]
+    fn step_back_test() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&COUNTER).unwrap();
+        let mut rewind = Rewind::new(10, usize::MAX);
+        let mut history = Vec::new();
+        for _ in 0..20 {
+            chip8.run_frame(7).unwrap();
+            rewind.push(&chip8);
+            history.push(chip8.save_state());
+        }
+        assert_eq!(rewind.len(), 19);
+
+        history.pop();
+        while let Some(expected) = history.pop() {
+            assert!(rewind.step_back(&mut chip8));
+            assert_eq!(chip8.save_state(), expected);
+        }
+        assert!(!rewind.step_back(&mut chip8));
+        assert!(rewind.is_empty());
+        assert_eq!(rewind.bytes(), 0);
+
+        // Recording carries on from the rewound frame
+        chip8.run_frame(7).unwrap();
+        rewind.push(&chip8);
+        assert!(rewind.step_back(&mut chip8));
+        assert_eq!(chip8.registers()[0], 2);
+    }
+
+    #[test]
+    fn limits_test() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&COUNTER).unwrap();
+        let mut rewind = Rewind::new(1, usize::MAX);
+        for _ in 0..100 {
+            chip8.run_frame(7).unwrap();
+            rewind.push(&chip8);
+        }
+        assert_eq!(rewind.len(), TIMER_HZ as usize);
+
+        // Each frame changes a register, a byte of memory and the screen,
+        // so a tiny cap keeps only a few frames
+        let mut rewind = Rewind::new(10, 1024);
+        for _ in 0..100 {
+            chip8.run_frame(7).unwrap();
+            rewind.push(&chip8);
+        }
+        assert!(rewind.bytes() <= 1024);
+        assert!(!rewind.is_empty() && rewind.len() < 100);
+
+        // Too long to count in frames is the same as no limit
+        let mut rewind = Rewind::new(u32::MAX, usize::MAX);
+        for _ in 0..3 {
+            chip8.run_frame(7).unwrap();
+            rewind.push(&chip8);
+        }
+        assert_eq!(rewind.len(), 2);
+    }
+}
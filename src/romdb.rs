@@ -0,0 +1,312 @@
+//! What is known about ROMs, by SHA-1, in the format of the community
+//! CHIP-8 database (<https://github.com/chip-8/chip-8-database>): a
+//! `sha1-hashes.json` mapping hashes to indexes into `programs.json`, and
+//! `platforms.json` giving each platform's quirks and speed.
+//!
+//! A few entries are bundled, enough for the ROMs shipped alongside the
+//! emulator. Platforms this emulator doesn't run (CHIP-8X, MegaChip) are
+//! skipped when picking the one a ROM runs on.
+
+use crate::json;
+use crate::value::Value;
+use crate::{Mode, Quirks};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+const BUNDLED_HASHES: &str = include_str!("../data/sha1-hashes.json");
+const BUNDLED_PROGRAMS: &str = include_str!("../data/programs.json");
+const BUNDLED_PLATFORMS: &str = include_str!("../data/platforms.json");
+
+/// Gamepad-style hints the database gives for which CHIP-8 key does what.
+pub const KEY_HINTS: [&str; 6] = ["up", "down", "left", "right", "a", "b"];
+
+#[derive(Debug, Clone, PartialEq)]
+struct Platform {
+    tickrate: Option<u32>,
+    quirks: Quirks,
+}
+
+/// The database entry for one ROM.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub release: Option<String>,
+    /// The database id of the platform the ROM runs on, e.g.
+    /// `"superchip"`, or `None` if it only lists ones this emulator lacks.
+    pub platform: Option<String>,
+    pub mode: Option<Mode>,
+    pub quirks: Option<Quirks>,
+    /// Instructions per frame.
+    pub tickrate: Option<u32>,
+    /// 0xRRGGBB colours for the background and each plane combination.
+    pub colors: Vec<u32>,
+    /// CHIP-8 keys for the [`KEY_HINTS`] the ROM gives.
+    pub keys: Vec<(String, u8)>,
+}
+
+impl RomInfo {
+    /// `"Title by Author (1979)"`.
+    pub fn byline(&self) -> String {
+        let mut line = self.title.clone();
+        if !self.authors.is_empty() {
+            line.push_str(&format!(" by {}", self.authors.join(", ")));
+        }
+        if let Some(release) = &self.release {
+            line.push_str(&format!(" ({release})"));
+        }
+        line
+    }
+
+    /// The settings this entry implies, as command-line arguments.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(mode) = self.mode {
+            let name = match mode {
+                Mode::Chip8 => "chip8",
+                Mode::SuperChip => "schip",
+                Mode::XoChip => "xochip",
+            };
+            args.push(format!("--mode={name}"));
+        }
+        if let Some(quirks) = self.quirks {
+            args.push(format!("--quirks={}", quirks.spec()));
+        }
+        if let Some(tickrate) = self.tickrate {
+            args.push(format!("--ipf={tickrate}"));
+        }
+        if self.colors.len() >= 2 {
+            // Colours past the four XO-CHIP plane combinations are for
+            // platforms this emulator doesn't run
+            let colors: Vec<String> = self
+                .colors
+                .iter()
+                .take(4)
+                .map(|c| format!("{c:06X}"))
+                .collect();
+            args.push(format!("--palette={}", colors.join(",")));
+        }
+        args
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RomDb {
+    hashes: BTreeMap<String, usize>,
+    programs: Vec<Value>,
+    platforms: BTreeMap<String, Platform>,
+}
+
+impl RomDb {
+    /// The database compiled into the emulator.
+    pub fn bundled() -> &'static RomDb {
+        static BUNDLED: OnceLock<RomDb> = OnceLock::new();
+        BUNDLED.get_or_init(|| {
+            RomDb::parse(BUNDLED_HASHES, BUNDLED_PROGRAMS, BUNDLED_PLATFORMS)
+                .expect("bundled ROM database is valid")
+        })
+    }
+
+    /// Reads the database from the contents of its three files.
+    pub fn parse(hashes: &str, programs: &str, platforms: &str) -> Result<RomDb, String> {
+        let hashes = json::parse(hashes).map_err(|e| format!("sha1-hashes.json: {e}"))?;
+        let programs = json::parse(programs).map_err(|e| format!("programs.json: {e}"))?;
+        let platforms = json::parse(platforms).map_err(|e| format!("platforms.json: {e}"))?;
+
+        let hashes = hashes
+            .as_table()
+            .ok_or("sha1-hashes.json: expected an object")?
+            .iter()
+            .filter_map(|(hash, index)| {
+                let index = usize::try_from(index.as_integer()?).ok()?;
+                Some((hash.to_ascii_lowercase(), index))
+            })
+            .collect();
+        let programs = programs
+            .as_array()
+            .ok_or("programs.json: expected an array")?
+            .to_vec();
+        let platforms = platforms
+            .as_array()
+            .ok_or("platforms.json: expected an array")?
+            .iter()
+            .filter_map(|platform| {
+                let id = platform.get("id")?.as_str()?;
+                let tickrate = platform.get("defaultTickrate").and_then(tickrate);
+                let quirks = apply_quirks(Quirks::default(), platform.get("quirks"));
+                Some((id.to_string(), Platform { tickrate, quirks }))
+            })
+            .collect();
+        Ok(RomDb {
+            hashes,
+            programs,
+            platforms,
+        })
+    }
+
+    /// The entry for the ROM with SHA-1 `hash`, if there is one.
+    pub fn lookup(&self, hash: &str) -> Option<RomInfo> {
+        let hash = hash.to_ascii_lowercase();
+        let program = self.programs.get(*self.hashes.get(&hash)?)?;
+        let rom = program.get("roms").and_then(|roms| roms.get(&hash));
+        let field = |name: &str| rom.and_then(|rom| rom.get(name));
+        let strings = |value: Option<&Value>| -> Vec<String> {
+            value
+                .and_then(Value::as_array)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|item| Some(item.as_str()?.to_string()))
+                .collect()
+        };
+
+        let platform = strings(field("platforms"))
+            .into_iter()
+            .find(|id| self.platforms.contains_key(id) && platform_mode(id).is_some());
+        let known = platform.as_ref().map(|id| &self.platforms[id]);
+        let quirks = platform.as_ref().zip(known).map(|(id, known)| {
+            let overrides = field("quirkyPlatforms").and_then(|q| q.get(id));
+            apply_quirks(known.quirks, overrides)
+        });
+        let colors = strings(field("colors").and_then(|c| c.get("pixels")))
+            .iter()
+            .filter_map(|c| {
+                let hex = c.trim_start_matches('#');
+                u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6)
+            })
+            .collect();
+        let keys = KEY_HINTS
+            .iter()
+            .filter_map(|&hint| {
+                let key = field("keys")?.get(hint)?.as_integer()?;
+                Some((
+                    hint.to_string(),
+                    u8::try_from(key).ok().filter(|&k| k < 16)?,
+                ))
+            })
+            .collect();
+
+        Some(RomInfo {
+            title: program.get("title")?.as_str()?.to_string(),
+            authors: strings(program.get("authors")),
+            release: program
+                .get("release")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            mode: platform.as_deref().and_then(platform_mode),
+            quirks,
+            tickrate: field("tickrate")
+                .and_then(tickrate)
+                .or(known.and_then(|p| p.tickrate)),
+            platform,
+            colors,
+            keys,
+        })
+    }
+}
+
+fn tickrate(value: &Value) -> Option<u32> {
+    u32::try_from(value.as_integer()?)
+        .ok()
+        .filter(|&rate| rate > 0)
+}
+
+/// The machine that runs a database platform.
+fn platform_mode(id: &str) -> Option<Mode> {
+    match id {
+        "originalChip8" | "hybridVIP" | "modernChip8" | "chip48" => Some(Mode::Chip8),
+        "superchip1" | "superchip" => Some(Mode::SuperChip),
+        "xochip" => Some(Mode::XoChip),
+        _ => None,
+    }
+}
+
+/// Sets the switches a database quirks object gives. Its `shift`, `wrap`
+/// and `memoryLeaveIUnchanged` are the opposites of ours; I incrementing by
+/// X rather than X + 1 isn't emulated and counts as incrementing.
+fn apply_quirks(mut quirks: Quirks, object: Option<&Value>) -> Quirks {
+    let flag = |name: &str| object.and_then(|q| q.get(name)).and_then(Value::as_bool);
+    if let Some(shift) = flag("shift") {
+        quirks.shift_uses_vy = !shift;
+    }
+    if let Some(leave) = flag("memoryLeaveIUnchanged") {
+        quirks.load_store_increments_i = !leave;
+    }
+    if let Some(wrap) = flag("wrap") {
+        quirks.clip_sprites = !wrap;
+    }
+    if let Some(jump) = flag("jump") {
+        quirks.jump_uses_vx = jump;
+    }
+    if let Some(vblank) = flag("vblank") {
+        quirks.display_wait = vblank;
+    }
+    if let Some(logic) = flag("logic") {
+        quirks.vf_reset = logic;
+    }
+    quirks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha1;
+
+    #[test]
+    fn bundled_test() {
+        let maze = sha1::hex_digest(include_bytes!("../maze.ch8"));
+        let info = RomDb::bundled().lookup(&maze).unwrap();
+        assert_eq!(info.byline(), "Maze by David Winter");
+        assert_eq!(info.platform.as_deref(), Some("originalChip8"));
+        assert_eq!(info.mode, Some(Mode::Chip8));
+        assert_eq!(info.quirks, Some(Quirks::vip()));
+        assert_eq!(info.tickrate, Some(15));
+
+        // The bundled platforms agree with the presets
+        let platforms = &RomDb::bundled().platforms;
+        assert_eq!(platforms["chip48"].quirks, Quirks::chip48());
+        assert_eq!(platforms["superchip"].quirks, Quirks::schip());
+        assert_eq!(platforms["xochip"].quirks, Quirks::xochip());
+
+        assert_eq!(RomDb::bundled().lookup(&sha1::hex_digest(b"")), None);
+    }
+
+    #[test]
+    fn lookup_test() {
+        let db = RomDb::parse(
+            r#"{"ABC": 0}"#,
+            r##"[{
+                "title": "Car",
+                "authors": ["Klaus von Sengbusch"],
+                "release": "1991",
+                "roms": {"abc": {
+                    "platforms": ["megachip8", "superchip"],
+                    "tickrate": 20,
+                    "colors": {
+                        "pixels": ["#101010", "#e0e0e0", "#fff", "#202020", "#303030", "#404040"],
+                        "buzzer": "#ff0000"
+                    },
+                    "keys": {"left": 7, "right": 9, "a": 16},
+                    "quirkyPlatforms": {"superchip": {"wrap": true}}
+                }}
+            }]"##,
+            BUNDLED_PLATFORMS,
+        )
+        .unwrap();
+        let info = db.lookup("abc").unwrap();
+        assert_eq!(info.platform.as_deref(), Some("superchip"));
+        assert_eq!(
+            info.keys,
+            [("left".to_string(), 7), ("right".to_string(), 9)]
+        );
+        assert_eq!(
+            info.args(),
+            [
+                "--mode=schip",
+                "--quirks=default,+jump",
+                "--ipf=20",
+                "--palette=101010,E0E0E0,202020,303030"
+            ]
+        );
+        assert_eq!(info.byline(), "Car by Klaus von Sengbusch (1991)");
+    }
+}
@@ -0,0 +1,52 @@
+use chip8_emu::{AudioSettings, AudioSink, SquareWave};
+use sdl2::AudioSubsystem;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+const SAMPLE_RATE: i32 = 44_100;
+
+struct Callback(SquareWave);
+
+impl AudioCallback for Callback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.0.fill(out);
+    }
+}
+
+/// Plays the buzzer through the default SDL audio device.
+pub struct SdlBeeper {
+    device: AudioDevice<Callback>,
+}
+
+impl SdlBeeper {
+    pub fn new(audio: &AudioSubsystem, settings: AudioSettings) -> Result<SdlBeeper, String> {
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = audio.open_playback(None, &desired, |spec| {
+            Callback(SquareWave::new(settings, spec.freq as u32))
+        })?;
+        device.resume();
+        Ok(SdlBeeper { device })
+    }
+
+    pub fn toggle_mute(&mut self) -> bool {
+        let mut callback = self.device.lock();
+        let muted = !callback.0.settings().muted;
+        callback.0.set_muted(muted);
+        muted
+    }
+}
+
+impl AudioSink for SdlBeeper {
+    fn update(&mut self, playing: bool) {
+        self.device.lock().0.set_playing(playing);
+    }
+
+    fn set_pattern(&mut self, pattern: [u8; 16], rate: f32) {
+        self.device.lock().0.set_pattern(pattern, rate);
+    }
+}
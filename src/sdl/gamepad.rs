@@ -0,0 +1,152 @@
+use chip8_emu::gamepad::{self, AxisTracker};
+use sdl2::GameControllerSubsystem;
+use sdl2::controller::GameController;
+use sdl2::event::Event;
+use std::collections::HashMap;
+
+struct Pad {
+    controller: GameController,
+    axes: AxisTracker,
+    /// Buttons held down, so they can be released if the pad is unplugged.
+    buttons: Vec<String>,
+}
+
+/// Opens controllers as they are plugged in and turns their events into
+/// keymap inputs (see [`chip8_emu::gamepad`]).
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    dead_zone: i16,
+    /// Open controllers by joystick instance id.
+    pads: HashMap<u32, Pad>,
+}
+
+impl Gamepads {
+    /// SDL reports controllers already connected as added on the first
+    /// poll, so there is nothing to open up front.
+    pub fn new(subsystem: GameControllerSubsystem, dead_zone: i16) -> Gamepads {
+        Gamepads {
+            subsystem,
+            dead_zone,
+            pads: HashMap::new(),
+        }
+    }
+
+    /// Handles a controller event, returning the inputs it pressed or
+    /// released as (name, pressed). Other events give nothing.
+    pub fn handle(&mut self, event: &Event) -> Vec<(String, bool)> {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                match self.subsystem.open(which) {
+                    Ok(controller) => {
+                        println!("[CHIP8] Controller connected: {}", controller.name());
+                        self.pads.insert(
+                            controller.instance_id(),
+                            Pad {
+                                controller,
+                                axes: AxisTracker::new(self.dead_zone),
+                                buttons: Vec::new(),
+                            },
+                        );
+                    }
+                    Err(err) => eprintln!("[CHIP8] Couldn't open controller {which}: {err}"),
+                }
+                Vec::new()
+            }
+            Event::ControllerDeviceRemoved { which, .. } => match self.pads.remove(&which) {
+                Some(mut pad) => {
+                    println!("[CHIP8] Controller disconnected: {}", pad.controller.name());
+                    let mut released = pad.axes.release_all();
+                    released.extend(pad.buttons.into_iter().map(|name| (name, false)));
+                    released
+                }
+                None => Vec::new(),
+            },
+            Event::ControllerButtonDown { which, button, .. } => {
+                let name = gamepad::button_name(&button.string());
+                if let Some(pad) = self.pads.get_mut(&which) {
+                    pad.buttons.push(name.clone());
+                }
+                vec![(name, true)]
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                let name = gamepad::button_name(&button.string());
+                if let Some(pad) = self.pads.get_mut(&which) {
+                    pad.buttons.retain(|held| *held != name);
+                }
+                vec![(name, false)]
+            }
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => match self.pads.get_mut(&which) {
+                Some(pad) => pad.axes.update(&axis.string(), value),
+                None => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_emu::gamepad::DEFAULT_DEAD_ZONE;
+    use sdl2::EventPump;
+    use sdl2::sys;
+
+    fn poll(pump: &mut EventPump, pads: &mut Gamepads) -> Vec<(String, bool)> {
+        let events: Vec<Event> = pump.poll_iter().collect();
+        events.iter().flat_map(|event| pads.handle(event)).collect()
+    }
+
+    /// Drives a virtual controller, so needs no hardware or display.
+    #[test]
+    fn virtual_controller_test() {
+        let sdl = sdl2::init().unwrap();
+        let subsystem = sdl.game_controller().unwrap();
+        let mut pump = sdl.event_pump().unwrap();
+        let mut pads = Gamepads::new(subsystem, DEFAULT_DEAD_ZONE);
+
+        // Laid out in SDL's controller button and axis order
+        let index = unsafe {
+            sys::SDL_JoystickAttachVirtual(
+                sys::SDL_JoystickType::SDL_JOYSTICK_TYPE_GAMECONTROLLER,
+                6,
+                15,
+                0,
+            )
+        };
+        assert!(index >= 0);
+        assert!(poll(&mut pump, &mut pads).is_empty());
+        assert_eq!(pads.pads.len(), 1);
+
+        let id = *pads.pads.keys().next().unwrap();
+        unsafe {
+            let joystick = sys::SDL_JoystickFromInstanceID(id as i32);
+            sys::SDL_JoystickSetVirtualButton(joystick, 0, 1);
+            sys::SDL_JoystickSetVirtualAxis(joystick, 1, -20000);
+            sys::SDL_JoystickSetVirtualAxis(joystick, 0, 1000);
+        }
+        let mut inputs = poll(&mut pump, &mut pads);
+        inputs.sort();
+        assert_eq!(
+            inputs,
+            [
+                ("Pad a".to_string(), true),
+                ("Pad lefty-".to_string(), true)
+            ]
+        );
+
+        // Unplugging releases whatever was held
+        unsafe { sys::SDL_JoystickDetachVirtual(index) };
+        let mut inputs = poll(&mut pump, &mut pads);
+        inputs.sort();
+        assert_eq!(
+            inputs,
+            [
+                ("Pad a".to_string(), false),
+                ("Pad lefty-".to_string(), false)
+            ]
+        );
+        assert!(pads.pads.is_empty());
+    }
+}
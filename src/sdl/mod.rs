@@ -0,0 +1,5 @@
+//! SDL2 backends for the pieces of the frontend that the core leaves
+//! abstract.
+
+pub mod audio;
+pub mod gamepad;
@@ -0,0 +1,214 @@
+//! Settings files: defaults for command-line options, kept in TOML (or
+//! JSON, for files ending in `.json`).
+//!
+//! ```toml
+//! scale = 4
+//! palette = ["000000", "33FF66"]
+//! keymap = "keymaps/qwertz.txt"
+//!
+//! # Per ROM, by the SHA-1 of the file
+//! [rom.0123456789abcdef0123456789abcdef01234567]
+//! quirks = "schip"
+//! ipf = 30
+//! ```
+//!
+//! Keys are option names without the leading `--`. Top-level keys apply to
+//! every ROM and a `[rom.<sha1>]` section overrides them for one ROM. Each
+//! becomes a `--name=value` argument placed before the real command line,
+//! so options given there win over any file.
+
+use crate::value::Value;
+use crate::{json, toml};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Options that can be set from a file. The rest only make sense for one
+/// run.
+pub const KEYS: [&str; 13] = [
+    "scale",
+    "ipf",
+    "mode",
+    "quirks",
+    "palette",
+    "frequency",
+    "volume",
+    "mute",
+    "seed",
+    "rewind",
+    "rewind-mem",
+    "keymap",
+    "dead-zone",
+];
+
+/// The project file, looked for in the working directory.
+pub const PROJECT_FILE: &str = "chip8.toml";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Settings {
+    global: BTreeMap<String, Value>,
+    /// Sections by lowercase ROM SHA-1.
+    roms: BTreeMap<String, BTreeMap<String, Value>>,
+}
+
+impl Settings {
+    pub fn from_value(doc: &Value) -> Result<Settings, String> {
+        let table = doc.as_table().ok_or("settings must be a table")?;
+        let mut settings = Settings::default();
+        for (key, value) in table {
+            if key == "rom" {
+                let roms = value.as_table().ok_or("`rom` must be a table of ROMs")?;
+                for (hash, section) in roms {
+                    let section = section
+                        .as_table()
+                        .ok_or(format!("`rom.{hash}` must be a table"))?;
+                    let mut options = BTreeMap::new();
+                    for (key, value) in section {
+                        check(key, value).map_err(|e| format!("rom.{hash}: {e}"))?;
+                        options.insert(key.clone(), value.clone());
+                    }
+                    settings.roms.insert(hash.to_ascii_lowercase(), options);
+                }
+            } else {
+                check(key, value)?;
+                settings.global.insert(key.clone(), value.clone());
+            }
+        }
+        Ok(settings)
+    }
+
+    /// Parses a settings file, as JSON if `path` ends in `.json` and TOML
+    /// otherwise.
+    pub fn read_file(path: &Path) -> Result<Settings, String> {
+        let name = path.display();
+        let src = fs::read_to_string(path).map_err(|e| format!("{name}: {e}"))?;
+        let doc = if path.extension().is_some_and(|ext| ext == "json") {
+            json::parse(&src)
+        } else {
+            toml::parse(&src)
+        };
+        doc.and_then(|doc| Settings::from_value(&doc))
+            .map_err(|e| format!("{name}: {e}"))
+    }
+
+    /// Reads and merges whichever of `paths` exist, later files overriding
+    /// earlier ones.
+    pub fn load(paths: &[PathBuf]) -> Result<Settings, String> {
+        let mut settings = Settings::default();
+        for path in paths.iter().filter(|path| path.is_file()) {
+            settings.merge(Settings::read_file(path)?);
+        }
+        Ok(settings)
+    }
+
+    /// Overrides these settings with `other`'s, key by key.
+    pub fn merge(&mut self, other: Settings) {
+        self.global.extend(other.global);
+        for (hash, options) in other.roms {
+            self.roms.entry(hash).or_default().extend(options);
+        }
+    }
+
+    /// The top-level settings as command-line arguments.
+    pub fn global_args(&self) -> Vec<String> {
+        args(&self.global)
+    }
+
+    /// The section for the ROM with SHA-1 `hash` as command-line arguments,
+    /// to go after [`Settings::global_args`] so that it wins.
+    pub fn rom_args(&self, hash: &str) -> Vec<String> {
+        self.roms
+            .get(&hash.to_ascii_lowercase())
+            .map(args)
+            .unwrap_or_default()
+    }
+}
+
+fn args(options: &BTreeMap<String, Value>) -> Vec<String> {
+    options
+        .iter()
+        .map(|(key, value)| format!("--{key}={value}"))
+        .collect()
+}
+
+fn check(key: &str, value: &Value) -> Result<(), String> {
+    if !KEYS.contains(&key) {
+        return Err(format!("unknown setting `{key}`"));
+    }
+    match value {
+        Value::Table(_) => Err(format!("`{key}` can't be a table")),
+        Value::Bool(_) if key != "mute" => Err(format!("`{key}` can't be true or false")),
+        _ => Ok(()),
+    }
+}
+
+/// The user's settings directory: `$XDG_CONFIG_HOME/chip8-emu`, or
+/// `~/.config/chip8-emu`.
+pub fn user_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("chip8-emu"))
+}
+
+/// Where settings are looked for, lowest priority first: the user's
+/// `config.toml` and `config.json`, then the project's `chip8.toml` and
+/// `chip8.json`.
+pub fn search_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Some(dir) = user_dir() {
+        paths.push(dir.join("config.toml"));
+        paths.push(dir.join("config.json"));
+    }
+    paths.push(PathBuf::from(PROJECT_FILE));
+    paths.push(Path::new(PROJECT_FILE).with_extension("json"));
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "da39a3ee5e6b4b0d3255bfef95601890afd80709";
+
+    #[test]
+    fn args_test() {
+        let user = toml::parse(&format!(
+            "scale = 4\nipf = 12\nmute = true\n\n[rom.{}]\nquirks = \"schip\"\nmute = false\n",
+            HASH.to_uppercase()
+        ))
+        .unwrap();
+        let mut settings = Settings::from_value(&user).unwrap();
+        let project = json::parse(&format!(
+            r#"{{"palette": ["000000", "FFB000"], "rom": {{"{HASH}": {{"ipf": 30}}}}}}"#
+        ))
+        .unwrap();
+        settings.merge(Settings::from_value(&project).unwrap());
+
+        assert_eq!(
+            settings.global_args(),
+            [
+                "--ipf=12",
+                "--mute=true",
+                "--palette=000000,FFB000",
+                "--scale=4"
+            ]
+        );
+        assert_eq!(
+            settings.rom_args(HASH),
+            ["--ipf=30", "--mute=false", "--quirks=schip"]
+        );
+        assert!(settings.rom_args("0000").is_empty());
+    }
+
+    #[test]
+    fn check_test() {
+        let err = |src: &str| Settings::from_value(&toml::parse(src).unwrap()).unwrap_err();
+        assert_eq!(err("trace = \"out.txt\""), "unknown setting `trace`");
+        assert_eq!(err("[rom.abc]\nfoo = 1"), "rom.abc: unknown setting `foo`");
+        assert_eq!(err("scale = true"), "`scale` can't be true or false");
+        assert_eq!(err("rom = 1"), "`rom` must be a table of ROMs");
+    }
+}
@@ -0,0 +1,325 @@
+//! Save states: everything needed to resume a [`crate::Chip8`] exactly where
+//! it was, and a versioned binary encoding for writing them to disk.
+//!
+//! The file is [`MAGIC`], a version byte, then the fields of
+//! [`SaveState`] in declaration order, little-endian. Each pixel is stored
+//! as one byte of plane bits and the generator name as a length byte and
+//! ASCII.
+
+use crate::chip8::{DISPLAY_HEIGHT, DISPLAY_WIDTH, HIRES_HEIGHT, HIRES_WIDTH, Mode};
+use crate::quirks::Quirks;
+use crate::random;
+use std::fs;
+use std::path::Path;
+
+pub const MAGIC: [u8; 4] = *b"C8ST";
+pub const VERSION: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveState {
+    pub mode: Mode,
+    pub quirks: Quirks,
+    pub registers: [u8; 16],
+    pub memory: Vec<u8>,
+    pub index: u16,
+    pub pc: usize,
+    pub stack: [u16; 16],
+    pub sp: usize,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keypad: [u8; 16],
+    pub video: Vec<u32>,
+    pub width: usize,
+    pub height: usize,
+    pub planes: u8,
+    pub audio_pattern: Option<[u8; 16]>,
+    pub pitch: u8,
+    pub flags: [u8; 16],
+    pub exited: bool,
+    pub vblank: bool,
+    pub cycles: u64,
+    /// The CXNN generator's name, seed and [`random::Random::state`].
+    pub rng: String,
+    pub seed: u64,
+    pub rng_state: u64,
+}
+
+pub(crate) fn mode_id(mode: Mode) -> u8 {
+    match mode {
+        Mode::Chip8 => 0,
+        Mode::SuperChip => 1,
+        Mode::XoChip => 2,
+    }
+}
+
+pub(crate) fn quirk_bits(quirks: Quirks) -> u8 {
+    [
+        quirks.shift_uses_vy,
+        quirks.load_store_increments_i,
+        quirks.jump_uses_vx,
+        quirks.clip_sprites,
+        quirks.vf_reset,
+        quirks.display_wait,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |bits, (i, &on)| bits | (on as u8) << i)
+}
+
+pub(crate) fn mode_from_id(id: u8) -> Result<Mode, String> {
+    match id {
+        0 => Ok(Mode::Chip8),
+        1 => Ok(Mode::SuperChip),
+        2 => Ok(Mode::XoChip),
+        id => Err(format!("unknown mode {id}")),
+    }
+}
+
+pub(crate) fn quirks_from_bits(bits: u8) -> Quirks {
+    let quirk = |i: u8| bits & (1 << i) != 0;
+    Quirks {
+        shift_uses_vy: quirk(0),
+        load_store_increments_i: quirk(1),
+        jump_uses_vx: quirk(2),
+        clip_sprites: quirk(3),
+        vf_reset: quirk(4),
+        display_wait: quirk(5),
+    }
+}
+
+impl SaveState {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.memory.len() + self.video.len() + 128);
+        out.extend_from_slice(&MAGIC);
+        out.push(VERSION);
+        out.push(mode_id(self.mode));
+        out.push(quirk_bits(self.quirks));
+        out.extend_from_slice(&self.registers);
+        out.extend_from_slice(&(self.memory.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.memory);
+        out.extend_from_slice(&self.index.to_le_bytes());
+        out.extend_from_slice(&(self.pc as u32).to_le_bytes());
+        for addr in self.stack {
+            out.extend_from_slice(&addr.to_le_bytes());
+        }
+        out.push(self.sp as u8);
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        out.extend_from_slice(&self.keypad);
+        out.push((self.width == HIRES_WIDTH) as u8);
+        out.extend(self.video.iter().map(|&pixel| pixel as u8));
+        out.push(self.planes);
+        match self.audio_pattern {
+            Some(pattern) => {
+                out.push(1);
+                out.extend_from_slice(&pattern);
+            }
+            None => out.push(0),
+        }
+        out.push(self.pitch);
+        out.extend_from_slice(&self.flags);
+        out.push(self.exited as u8);
+        out.push(self.vblank as u8);
+        out.extend_from_slice(&self.cycles.to_le_bytes());
+        out.push(self.rng.len() as u8);
+        out.extend_from_slice(self.rng.as_bytes());
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        out.extend_from_slice(&self.rng_state.to_le_bytes());
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<SaveState, String> {
+        let mut r = Reader::new(bytes, "save state");
+        if r.take(4)? != MAGIC {
+            return Err("not a save state".to_string());
+        }
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(format!("unsupported save state version {version}"));
+        }
+        let mode = mode_from_id(r.u8()?)?;
+        let quirks = quirks_from_bits(r.u8()?);
+        let registers = r.array()?;
+        let memory_len = u32::from_le_bytes(r.array()?) as usize;
+        if memory_len != mode.memory_size() {
+            return Err(format!("{memory_len} bytes of memory in {mode:?} mode"));
+        }
+        let memory = r.take(memory_len)?.to_vec();
+        let index = u16::from_le_bytes(r.array()?);
+        let pc = u32::from_le_bytes(r.array()?) as usize;
+        let mut stack = [0; 16];
+        for addr in &mut stack {
+            *addr = u16::from_le_bytes(r.array()?);
+        }
+        let sp = r.u8()? as usize;
+        if sp > stack.len() {
+            return Err(format!("stack pointer {sp} out of range"));
+        }
+        let delay_timer = r.u8()?;
+        let sound_timer = r.u8()?;
+        let keypad = r.array()?;
+        let (width, height) = match r.u8()? {
+            0 => (DISPLAY_WIDTH, DISPLAY_HEIGHT),
+            _ => (HIRES_WIDTH, HIRES_HEIGHT),
+        };
+        let video = r.take(width * height)?.iter().map(|&p| p as u32).collect();
+        let planes = r.u8()?;
+        let audio_pattern = match r.u8()? {
+            0 => None,
+            _ => Some(r.array()?),
+        };
+        let pitch = r.u8()?;
+        let flags = r.array()?;
+        let exited = r.u8()? != 0;
+        let vblank = r.u8()? != 0;
+        let cycles = u64::from_le_bytes(r.array()?);
+        let rng = r.string()?;
+        if random::from_name(&rng, 0).is_none() {
+            return Err(format!("unknown generator {rng}"));
+        }
+        let seed = u64::from_le_bytes(r.array()?);
+        let rng_state = u64::from_le_bytes(r.array()?);
+        r.finish()?;
+        Ok(SaveState {
+            mode,
+            quirks,
+            registers,
+            memory,
+            index,
+            pc,
+            stack,
+            sp,
+            delay_timer,
+            sound_timer,
+            keypad,
+            video,
+            width,
+            height,
+            planes,
+            audio_pattern,
+            pitch,
+            flags,
+            exited,
+            vblank,
+            cycles,
+            rng,
+            seed,
+            rng_state,
+        })
+    }
+
+    /// Writes the encoded state to `path`.
+    pub fn write_file(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        fs::write(path, self.encode()).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn read_file(path: impl AsRef<Path>) -> Result<SaveState, String> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        SaveState::decode(&bytes).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+/// Reads the fields of a binary file, naming the file kind in errors.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    what: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8], what: &'static str) -> Reader<'a> {
+        Reader {
+            bytes,
+            pos: 0,
+            what,
+        }
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| format!("{} is truncated", self.what))?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    /// Fails if anything is left after the last field.
+    pub(crate) fn finish(&self) -> Result<(), String> {
+        if self.pos != self.bytes.len() {
+            return Err(format!("trailing bytes after {}", self.what));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// A string stored as a length byte and its bytes.
+    pub(crate) fn string(&mut self) -> Result<String, String> {
+        let len = self.u8()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip8;
+
+    #[test]
+    fn round_trip_test() {
+        // HIGH; LD V0, 0x07; LD ST, V0; LD I, font 0; DRW V0, V0, 5; CALL 0x20C; JP 0x20C
+        let rom = [
+            0x00, 0xFF, 0x60, 0x07, 0xF0, 0x18, 0xF1, 0x29, 0xD0, 0x05, 0x22, 0x0C, 0x12, 0x0C,
+        ];
+        let mut chip8 = Chip8::with_mode(Mode::SuperChip, Quirks::schip());
+        chip8.load_rom_bytes(&rom).unwrap();
+        chip8.set_seed(9);
+        chip8.set_key(0xA, true);
+        chip8.run_frame(6).unwrap();
+
+        let state = chip8.save_state();
+        let bytes = state.encode();
+        assert_eq!(SaveState::decode(&bytes).as_ref(), Ok(&state));
+
+        let mut restored = Chip8::new();
+        restored.load_state(SaveState::decode(&bytes).unwrap());
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.display_width(), HIRES_WIDTH);
+        assert_eq!(restored.keypad()[0xA], 1);
+        assert_eq!(restored.sp(), 1);
+        assert_eq!(restored.seed(), 9);
+
+        // Both machines carry on identically
+        chip8.run_frame(3).unwrap();
+        restored.run_frame(3).unwrap();
+        assert_eq!(restored.save_state(), chip8.save_state());
+    }
+
+    #[test]
+    fn decode_errors_test() {
+        let bytes = Chip8::new().save_state().encode();
+        assert_eq!(
+            SaveState::decode(&bytes[..100]),
+            Err("save state is truncated".to_string())
+        );
+        assert_eq!(
+            SaveState::decode(b"PNG!\x01"),
+            Err("not a save state".to_string())
+        );
+        let mut future = bytes.clone();
+        future[4] = 99;
+        assert_eq!(
+            SaveState::decode(&future),
+            Err("unsupported save state version 99".to_string())
+        );
+    }
+}
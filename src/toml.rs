@@ -0,0 +1,297 @@
+//! The subset of TOML settings files need: tables with dotted headers,
+//! dotted keys, strings, integers, floats, booleans and arrays. Arrays of
+//! tables, inline tables and dates are not supported.
+
+use crate::value::Value;
+use std::collections::BTreeMap;
+
+type Table = BTreeMap<String, Value>;
+
+pub fn parse(src: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        chars: src.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    let mut root = Table::new();
+    let mut current: Vec<String> = Vec::new();
+    loop {
+        parser.skip_blank(true);
+        let Some(c) = parser.peek() else {
+            break;
+        };
+        if c == '[' {
+            parser.pos += 1;
+            if parser.peek() == Some('[') {
+                return Err(parser.error("arrays of tables are not supported"));
+            }
+            current = parser.key()?;
+            parser.expect(']')?;
+            table_at(&mut root, &current).map_err(|msg| parser.error(&msg))?;
+        } else {
+            let mut path = parser.key()?;
+            parser.expect('=')?;
+            let value = parser.value()?;
+            let name = path.pop().expect("keys have at least one part");
+            let mut full = current.clone();
+            full.extend(path);
+            let table = table_at(&mut root, &full).map_err(|msg| parser.error(&msg))?;
+            if table.insert(name.clone(), value).is_some() {
+                return Err(parser.error(&format!("duplicate key `{name}`")));
+            }
+        }
+        parser.end_of_line()?;
+    }
+    Ok(Value::Table(root))
+}
+
+/// The table at `path`, created if it doesn't exist yet.
+fn table_at<'a>(root: &'a mut Table, path: &[String]) -> Result<&'a mut Table, String> {
+    let mut table = root;
+    for part in path {
+        let entry = table
+            .entry(part.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        table = match entry {
+            Value::Table(inner) => inner,
+            _ => return Err(format!("`{part}` is not a table")),
+        };
+    }
+    Ok(table)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn error(&self, msg: &str) -> String {
+        format!("line {}: {msg}", self.line)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    /// Skips spaces and comments, and newlines too if `newlines`.
+    fn skip_blank(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => self.pos += 1,
+                '\n' if newlines => {
+                    self.next();
+                }
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_blank(false);
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(&format!("expected `{expected}`, found `{c}`"))),
+            None => Err(self.error(&format!("expected `{expected}`"))),
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_blank(false);
+        match self.next() {
+            None | Some('\n') => Ok(()),
+            Some(c) => Err(self.error(&format!("unexpected `{c}` after value"))),
+        }
+    }
+
+    /// A dotted key such as `rom."1a2b".quirks`.
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_blank(false);
+            let part = match self.peek() {
+                Some('"') | Some('\'') => self.string()?,
+                _ => {
+                    let start = self.pos;
+                    while self
+                        .peek()
+                        .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                    {
+                        self.pos += 1;
+                    }
+                    if start == self.pos {
+                        return Err(self.error("expected a key"));
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                }
+            };
+            parts.push(part);
+            self.skip_blank(false);
+            if self.peek() != Some('.') {
+                return Ok(parts);
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_blank(false);
+        match self.peek() {
+            Some('"') | Some('\'') => Ok(Value::String(self.string()?)),
+            Some('[') => self.array(),
+            Some('{') => Err(self.error("inline tables are not supported")),
+            Some(_) => self.scalar(),
+            None => Err(self.error("expected a value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.next().expect("called on a quote");
+        let mut out = String::new();
+        loop {
+            match self.next() {
+                None | Some('\n') => return Err(self.error("unterminated string")),
+                Some(c) if c == quote => return Ok(out),
+                Some('\\') if quote == '"' => {
+                    let escaped = match self.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('u') => {
+                            let hex: String = (0..4).filter_map(|_| self.next()).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error("invalid unicode escape"))?
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    out.push(escaped);
+                }
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank(true);
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_blank(true);
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return Err(self.error("expected `,` or `]` in array")),
+            }
+        }
+    }
+
+    fn scalar(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || "_+-.".contains(c))
+        {
+            self.pos += 1;
+        }
+        let token: String = self.chars[start..self.pos].iter().collect();
+        let digits = token.replace('_', "");
+        let (sign, unsigned) = match digits.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, digits.trim_start_matches('+')),
+        };
+        let radix = match unsigned.get(..2) {
+            Some("0x") => Some(16),
+            Some("0o") => Some(8),
+            Some("0b") => Some(2),
+            _ => None,
+        };
+        let value = match token.as_str() {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            _ => match radix {
+                Some(radix) => i64::from_str_radix(&unsigned[2..], radix)
+                    .ok()
+                    .map(|n| Value::Integer(sign * n)),
+                None if unsigned.contains(['.', 'e', 'E']) => digits.parse().ok().map(Value::Float),
+                None => digits.parse().ok().map(Value::Integer),
+            },
+        };
+        value.ok_or_else(|| self.error(&format!("invalid value `{token}`")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let src = r#"
+# defaults
+scale = 8
+volume = 0.25
+mute = false
+palette = ["000000", 'FFB000',   # amber
+]
+
+[rom.da39a3ee]
+quirks = "schip"
+ipf = 0x1_E
+keys."Left Shift" = -1
+"#;
+        let doc = parse(src).unwrap();
+        assert_eq!(doc.get("scale"), Some(&Value::Integer(8)));
+        assert_eq!(doc.get("volume"), Some(&Value::Float(0.25)));
+        assert_eq!(doc.get("mute").and_then(Value::as_bool), Some(false));
+        assert_eq!(doc.get("palette").unwrap().to_string(), "000000,FFB000");
+
+        let rom = doc.get("rom").and_then(|r| r.get("da39a3ee")).unwrap();
+        assert_eq!(rom.get("quirks").and_then(Value::as_str), Some("schip"));
+        assert_eq!(rom.get("ipf").and_then(Value::as_integer), Some(30));
+        let keys = rom.get("keys").unwrap();
+        assert_eq!(keys.get("Left Shift"), Some(&Value::Integer(-1)));
+    }
+
+    #[test]
+    fn errors_test() {
+        assert_eq!(
+            parse("a = 1\na = 2"),
+            Err("line 2: duplicate key `a`".to_string())
+        );
+        assert_eq!(
+            parse("a = \"open"),
+            Err("line 1: unterminated string".to_string())
+        );
+        assert_eq!(
+            parse("a = 1 2"),
+            Err("line 1: unexpected `2` after value".to_string())
+        );
+        assert!(parse("a = 1\n[a]").is_err());
+        assert!(parse("[[rom]]").is_err());
+        assert!(parse("a = nope").is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! Opt-in instruction tracing. When a [`TraceFilter`] is set on a
+//! [`crate::Chip8`], every matching instruction leaves a [`TraceRecord`]
+//! behind; frontends drain them and hand them to a [`TraceSink`] such as a
+//! [`TraceWriter`].
+
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::ops::Range;
+
+/// Written at the start of a binary trace, followed by a version byte.
+pub const BINARY_MAGIC: [u8; 4] = *b"C8TR";
+pub const BINARY_VERSION: u8 = 1;
+
+/// One executed instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// Instructions executed before this one.
+    pub cycle: u64,
+    pub pc: usize,
+    pub opcode: u16,
+    pub instruction: String,
+    /// Registers the instruction changed, as (register, old, new).
+    pub changes: Vec<(u8, u8, u8)>,
+    /// I and SP after the instruction.
+    pub index: u16,
+    pub sp: usize,
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>8} 0x{:03X} {:04X} {:<20} I=0x{:03X} SP={}",
+            self.cycle, self.pc, self.opcode, self.instruction, self.index, self.sp
+        )?;
+        for &(reg, old, new) in &self.changes {
+            write!(f, " V{reg:X}:{old:02X}->{new:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Which instructions to trace.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Only trace instructions at these addresses.
+    pub range: Option<Range<usize>>,
+    /// Only trace opcodes whose top nibble has its bit set here, e.g.
+    /// `1 << 0xD` for draws. Zero traces every class.
+    pub classes: u16,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, pc: usize, opcode: u16) -> bool {
+        self.range.as_ref().is_none_or(|range| range.contains(&pc))
+            && (self.classes == 0 || self.classes & (1 << (opcode >> 12)) != 0)
+    }
+
+    /// Parses an inclusive address range such as `0x200-0x2FF`.
+    pub fn parse_range(spec: &str) -> Result<Range<usize>, String> {
+        let parse = |s: &str| {
+            usize::from_str_radix(s.trim().trim_start_matches("0x"), 16)
+                .map_err(|_| format!("invalid address: {s}"))
+        };
+        let (start, end) = spec
+            .split_once('-')
+            .ok_or_else(|| format!("expected START-END, got {spec}"))?;
+        let (start, end) = (parse(start)?, parse(end)?);
+        if end < start {
+            return Err(format!("empty range: {spec}"));
+        }
+        Ok(start..end + 1)
+    }
+
+    /// Parses a comma-separated list of opcode classes, each the hex top
+    /// nibble of the opcode (`8` for ALU ops, `D` for draws, `F` for misc).
+    pub fn parse_classes(spec: &str) -> Result<u16, String> {
+        spec.split(',').try_fold(0, |mask, class| {
+            let nibble = u8::from_str_radix(class.trim(), 16)
+                .ok()
+                .filter(|&n| n < 16)
+                .ok_or_else(|| format!("invalid opcode class: {class}"))?;
+            Ok(mask | 1 << nibble)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One aligned line per instruction.
+    #[default]
+    Text,
+    /// A header row, then one row per instruction.
+    Csv,
+    /// [`BINARY_MAGIC`] and a version byte, then per instruction: cycle
+    /// (u64), PC, opcode and I (u16), SP and change count (u8), and a
+    /// (register, old, new) byte triple per change. Little-endian; the
+    /// mnemonic is left out since the opcode determines it.
+    Binary,
+}
+
+impl TraceFormat {
+    pub fn from_name(name: &str) -> Option<TraceFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "text" | "txt" => Some(TraceFormat::Text),
+            "csv" => Some(TraceFormat::Csv),
+            "bin" | "binary" => Some(TraceFormat::Binary),
+            _ => None,
+        }
+    }
+}
+
+pub trait TraceSink {
+    fn record(&mut self, record: &TraceRecord);
+}
+
+impl TraceSink for Vec<TraceRecord> {
+    fn record(&mut self, record: &TraceRecord) {
+        self.push(record.clone());
+    }
+}
+
+/// Encodes records to a writer. Like `BufWriter`, write errors are held
+/// until [`TraceWriter::finish`] rather than reported per record.
+#[derive(Debug)]
+pub struct TraceWriter<W: Write> {
+    out: W,
+    format: TraceFormat,
+    started: bool,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(out: W, format: TraceFormat) -> TraceWriter<W> {
+        TraceWriter {
+            out,
+            format,
+            started: false,
+            error: None,
+        }
+    }
+
+    fn header(&mut self) -> io::Result<()> {
+        match self.format {
+            TraceFormat::Text => Ok(()),
+            TraceFormat::Csv => writeln!(self.out, "cycle,pc,opcode,instruction,index,sp,changes"),
+            TraceFormat::Binary => {
+                self.out.write_all(&BINARY_MAGIC)?;
+                self.out.write_all(&[BINARY_VERSION])
+            }
+        }
+    }
+
+    fn write(&mut self, record: &TraceRecord) -> io::Result<()> {
+        if !self.started {
+            self.started = true;
+            self.header()?;
+        }
+        match self.format {
+            TraceFormat::Text => writeln!(self.out, "{record}"),
+            TraceFormat::Csv => {
+                let mut changes = String::new();
+                for (i, &(reg, _, new)) in record.changes.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { ";" };
+                    let _ = write!(changes, "{sep}V{reg:X}={new:02X}");
+                }
+                writeln!(
+                    self.out,
+                    "{},0x{:03X},{:04X},\"{}\",0x{:03X},{},{changes}",
+                    record.cycle,
+                    record.pc,
+                    record.opcode,
+                    record.instruction,
+                    record.index,
+                    record.sp
+                )
+            }
+            TraceFormat::Binary => {
+                let mut bytes = Vec::with_capacity(16 + 3 * record.changes.len());
+                bytes.extend_from_slice(&record.cycle.to_le_bytes());
+                bytes.extend_from_slice(&(record.pc as u16).to_le_bytes());
+                bytes.extend_from_slice(&record.opcode.to_le_bytes());
+                bytes.extend_from_slice(&record.index.to_le_bytes());
+                bytes.push(record.sp as u8);
+                bytes.push(record.changes.len() as u8);
+                for &(reg, old, new) in &record.changes {
+                    bytes.extend_from_slice(&[reg, old, new]);
+                }
+                self.out.write_all(&bytes)
+            }
+        }
+    }
+
+    /// Flushes the writer and returns it, or the first error hit while
+    /// writing.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+impl<W: Write> TraceSink for TraceWriter<W> {
+    fn record(&mut self, record: &TraceRecord) {
+        if self.error.is_none()
+            && let Err(err) = self.write(record)
+        {
+            self.error = Some(err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip8;
+
+    #[test]
+    fn filter_test() {
+        let filter = TraceFilter {
+            range: Some(TraceFilter::parse_range("0x200-0x203").unwrap()),
+            classes: TraceFilter::parse_classes("6, d").unwrap(),
+        };
+        assert!(filter.matches(0x202, 0x6001));
+        assert!(filter.matches(0x200, 0xD125));
+        assert!(!filter.matches(0x204, 0x6001));
+        assert!(!filter.matches(0x200, 0x7001));
+        assert!(TraceFilter::default().matches(0xFFF, 0x0000));
+        assert!(TraceFilter::parse_range("0x300-0x200").is_err());
+        assert!(TraceFilter::parse_classes("G").is_err());
+    }
+
+    #[test]
+    fn record_test() {
+        // LD V0, 0x05; LD I, 0x300; ADD V0, V0
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom_bytes(&[0x60, 0x05, 0xA3, 0x00, 0x80, 0x04])
+            .unwrap();
+        chip8.cycle().unwrap();
+        chip8.set_trace(Some(TraceFilter::default()));
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+
+        let records = chip8.take_trace();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].cycle, 1);
+        assert_eq!(records[0].instruction, "LD I, $0x300");
+        assert!(records[0].changes.is_empty());
+        assert_eq!(records[1].changes, [(0, 0x05, 0x0A)]);
+        assert!(chip8.take_trace().is_empty());
+
+        let mut text = TraceWriter::new(Vec::new(), TraceFormat::Text);
+        text.record(&records[1]);
+        let text = String::from_utf8(text.finish().unwrap()).unwrap();
+        assert_eq!(
+            text,
+            "       2 0x204 8004 ADD V0, V0           I=0x300 SP=0 V0:05->0A\n"
+        );
+
+        let mut csv = TraceWriter::new(Vec::new(), TraceFormat::Csv);
+        csv.record(&records[0]);
+        let csv = String::from_utf8(csv.finish().unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "cycle,pc,opcode,instruction,index,sp,changes\n1,0x202,A300,\"LD I, $0x300\",0x300,0,\n"
+        );
+
+        let mut binary = TraceWriter::new(Vec::new(), TraceFormat::Binary);
+        binary.record(&records[1]);
+        let binary = binary.finish().unwrap();
+        assert_eq!(binary[..5], *b"C8TR\x01");
+        assert_eq!(binary.len(), 5 + 16 + 3);
+    }
+}
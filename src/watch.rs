@@ -0,0 +1,81 @@
+//! Memory watchpoints. Every data access made by an instruction goes through
+//! [`crate::Chip8`]'s access layer, which records a [`WatchHit`] for each
+//! watchpoint it matches. Instruction fetches are not data accesses and are
+//! never reported.
+
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: Range<usize>,
+    pub on_read: bool,
+    pub on_write: bool,
+    /// Only record the access instead of stopping execution.
+    pub log_only: bool,
+}
+
+impl Watchpoint {
+    pub fn matches(&self, addr: usize, access: Access) -> bool {
+        self.range.contains(&addr)
+            && match access {
+                Access::Read => self.on_read,
+                Access::Write => self.on_write,
+            }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match (self.on_read, self.on_write) {
+            (true, true) => "rw",
+            (true, false) => "r",
+            (false, true) => "w",
+            (false, false) => "-",
+        };
+        write!(
+            f,
+            "watch 0x{:03X}-0x{:03X} {kind}",
+            self.range.start,
+            self.range.end.saturating_sub(1)
+        )?;
+        if self.log_only {
+            write!(f, " log")?;
+        }
+        Ok(())
+    }
+}
+
+/// One access that matched a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Index of the watchpoint that matched.
+    pub watchpoint: usize,
+    pub access: Access,
+    pub addr: usize,
+    /// The byte read, or the byte written.
+    pub value: u8,
+    /// Address and opcode of the instruction that made the access.
+    pub pc: usize,
+    pub opcode: u16,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = match self.access {
+            Access::Read => "read",
+            Access::Write => "write",
+        };
+        write!(
+            f,
+            "watchpoint {}: {verb} 0x{:02X} @ 0x{:03X} by 0x{:03X} [{:04X}]",
+            self.watchpoint, self.value, self.addr, self.pc, self.opcode
+        )
+    }
+}